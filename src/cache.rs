@@ -0,0 +1,263 @@
+//! Report cache of `--cache`, keeping the analysis of every file between
+//! runs. Version 1 is a text file with one tab separated line per file:
+//!
+//! ```text
+//! # media-server-diff cache v1 <options>
+//! <size> <modified> <report>
+//! ```
+//!
+//! - `options`: hash of the analysis options the reports were made with, a
+//!   cache written with other options isn't used
+//! - `size`: in bytes
+//! - `modified`: modification time in nanoseconds since the Unix epoch
+//! - `report`: the `MediaReport` as a single line of JSON, path included
+//!
+//! Entries are sorted by path.
+
+use crate::report::MediaReport;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::UNIX_EPOCH;
+
+/// Version of the cache format written by `render`
+pub const VERSION: u32 = 1;
+/// First line of every cache, followed by the version number and the options
+const HEADER_PREFIX: &str = "# media-server-diff cache v";
+
+/// Size and modification time of a file, which tell whether it changed
+/// since it was cached without opening it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub size: u64,
+    pub modified: u128,
+}
+
+impl Stamp {
+    /// `None` when the file system doesn't keep modification times
+    pub fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            size: metadata.len(),
+            modified: modified.as_nanos(),
+        })
+    }
+}
+
+/// A single file of the cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub stamp: Stamp,
+    pub report: MediaReport,
+}
+
+/// The reports of the previous run, looked up by the workers, and the ones
+/// of this run, written back with `save` once the scan is complete
+pub struct Cache {
+    options: String,
+    previous: HashMap<PathBuf, Entry>,
+    current: Mutex<Vec<Entry>>,
+}
+
+impl Cache {
+    /// An empty cache for reports made with `options`
+    pub fn new(options: &str) -> Cache {
+        Cache {
+            options: options.to_owned(),
+            previous: HashMap::new(),
+            current: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Read the cache written by a previous run, empty if there is none yet
+    pub fn load(path: &Path, options: &str) -> Result<Cache, String> {
+        let mut cache = Cache::new(options);
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                cache.previous = parse(&contents, options)?
+                    .into_iter()
+                    .map(|entry| (entry.report.path.clone(), entry))
+                    .collect();
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.to_string()),
+        }
+        Ok(cache)
+    }
+
+    /// The cached report of a file, if it is still the same size and has
+    /// the same modification time
+    pub fn get(&self, path: &Path, stamp: Stamp) -> Option<&MediaReport> {
+        self.previous
+            .get(path)
+            .filter(|entry| entry.stamp == stamp)
+            .map(|entry| &entry.report)
+    }
+
+    /// Keep the report of a file of this run
+    pub fn insert(&self, entry: Entry) {
+        self.current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(entry);
+    }
+
+    /// Replace the cache with the reports of this run, dropping the files
+    /// that weren't scanned again
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        fs::write(path, render(&self.options, &current))
+    }
+}
+
+/// Render the entries as a cache of the current version
+pub fn render(options: &str, entries: &[Entry]) -> String {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.report.path.cmp(&b.report.path));
+
+    let mut cache = format!("{}{} {}\n", HEADER_PREFIX, VERSION, options);
+    for entry in sorted {
+        cache.push_str(&format!(
+            "{}\t{}\t{}\n",
+            entry.stamp.size,
+            entry.stamp.modified,
+            serde_json::to_string(&entry.report).expect("reports serialize to JSON")
+        ));
+    }
+
+    cache
+}
+
+/// Parse a cache, rejecting versions this build doesn't understand and
+/// caches written with other analysis options than `options`
+pub fn parse(contents: &str, options: &str) -> Result<Vec<Entry>, String> {
+    let mut lines = contents.lines().enumerate();
+
+    let header = lines
+        .next()
+        .and_then(|(_, header)| header.strip_prefix(HEADER_PREFIX))
+        .ok_or_else(|| String::from("not a media-server-diff cache"))?;
+    let (version, written_with) = header.split_once(' ').unwrap_or((header, ""));
+    if version != VERSION.to_string() {
+        return Err(format!("unsupported cache version {}", version));
+    }
+    if written_with != options {
+        return Err(String::from("written with other analysis options"));
+    }
+
+    let mut entries = Vec::new();
+    for (index, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let invalid = || format!("line {}: invalid entry", index + 1);
+        let mut columns = line.splitn(3, '\t');
+        if let (Some(size), Some(modified), Some(report)) =
+            (columns.next(), columns.next(), columns.next())
+        {
+            entries.push(Entry {
+                stamp: Stamp {
+                    size: size.parse().map_err(|_| invalid())?,
+                    modified: modified.parse().map_err(|_| invalid())?,
+                },
+                report: serde_json::from_str(report).map_err(|_| invalid())?,
+            });
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{Marker, StreamKind, StreamReport};
+    use std::time::Duration;
+
+    fn entry(path: &str) -> Entry {
+        Entry {
+            stamp: Stamp {
+                size: 2_700_250_000,
+                modified: 1_700_000_000_123_456_789,
+            },
+            report: MediaReport {
+                path: PathBuf::from(path),
+                format_name: String::from("matroska,webm"),
+                format_flags: Some(vec!["seekable"]),
+                duration: Some(Duration::from_millis(5400500)),
+                size: 2_700_250_000,
+                streams: vec![StreamReport {
+                    kind: StreamKind::Video,
+                    codec: String::from("hevc"),
+                    resolution: Some((1920, 1080)),
+                    ..StreamReport::default()
+                }],
+                best_video: Some(0),
+                markers: vec![Marker {
+                    label: "START-SPREAD",
+                    reason: String::from("the audio and video streams start 0.500s apart"),
+                }],
+                ..MediaReport::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![
+            entry("/library/tv/Show\tName.mkv"),
+            entry("/library/movies/Heat.mkv"),
+        ];
+
+        let rendered = render("0123456789abcdef", &entries);
+        assert!(rendered.starts_with("# media-server-diff cache v1 0123456789abcdef\n"));
+        assert_eq!(rendered.lines().count(), 3);
+
+        let mut parsed = parse(&rendered, "0123456789abcdef").unwrap();
+        parsed.reverse();
+        assert_eq!(parsed, entries);
+    }
+
+    #[test]
+    fn test_rejects_other_options() {
+        let rendered = render("0123456789abcdef", &[entry("/library/Heat.mkv")]);
+        assert!(parse(&rendered, "fedcba9876543210").is_err());
+        assert!(parse(
+            "# media-server-diff cache v2 0123456789abcdef\n",
+            "0123456789abcdef"
+        )
+        .is_err());
+        assert!(parse(
+            "# media-server-diff cache v1 0123456789abcdef\nbroken\n",
+            "0123456789abcdef"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut cache = Cache::new("0123456789abcdef");
+        let cached = entry("/library/Heat.mkv");
+        cache
+            .previous
+            .insert(cached.report.path.clone(), cached.clone());
+
+        let path = Path::new("/library/Heat.mkv");
+        assert_eq!(cache.get(path, cached.stamp), Some(&cached.report));
+        let touched = Stamp {
+            modified: cached.stamp.modified + 1,
+            ..cached.stamp
+        };
+        assert_eq!(cache.get(path, touched), None);
+        assert_eq!(
+            cache.get(Path::new("/library/Ronin.mkv"), cached.stamp),
+            None
+        );
+    }
+}
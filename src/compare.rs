@@ -0,0 +1,534 @@
+use crate::report::{FieldDiff, MediaReport};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Placeholder for a value that only exists in the other copy
+const MISSING: &str = "-";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+/// Number of fields listed in the summary
+const TOP_FIELDS: usize = 10;
+
+/// Difference between the two copies of a single file, keyed by the path
+/// relative to the root of each copy
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Only present in the first copy
+    Removed(PathBuf),
+    /// Only present in the second copy
+    Added(PathBuf),
+    /// Present in both copies, with the fields that differ and the unchanged
+    /// fields kept as context
+    Changed(PathBuf, Vec<FieldDiff>),
+    /// Only present under a different path in each copy, from the first
+    /// copy's path to the second's
+    Renamed(PathBuf, PathBuf),
+}
+
+/// Reports present in one or both copies, keyed by their relative path
+type Pairs<'a> = BTreeMap<&'a Path, (Option<&'a MediaReport>, Option<&'a MediaReport>)>;
+
+/// Paths in the first and in the second copy sharing a size and structure
+type RenameCandidates<'a> = (Vec<&'a Path>, Vec<&'a Path>);
+
+/// Match up the reports of two copies of a library by their path relative
+/// to each root, returning the changes in path order. Changed files keep up
+/// to `context` unchanged fields on either side of every changed one. With
+/// `detect_renames` a file only in the first copy and one only in the second
+/// are reported as a rename when their size and structure match
+pub fn compare(
+    a_root: &Path,
+    a_reports: &[MediaReport],
+    b_root: &Path,
+    b_reports: &[MediaReport],
+    context: usize,
+    detect_renames: bool,
+) -> Vec<Change> {
+    let mut pairs: Pairs = BTreeMap::new();
+    for report in a_reports {
+        pairs.entry(report.relative_path(a_root)).or_default().0 = Some(report);
+    }
+    for report in b_reports {
+        pairs.entry(report.relative_path(b_root)).or_default().1 = Some(report);
+    }
+
+    let renames = if detect_renames {
+        match_renames(&pairs)
+    } else {
+        HashMap::new()
+    };
+    let renamed_to: HashSet<&Path> = renames.values().copied().collect();
+
+    pairs
+        .into_iter()
+        .filter_map(|(path, pair)| match pair {
+            (Some(a), Some(b)) => {
+                let fields = if context == 0 {
+                    MediaReport::diff(a, b)
+                } else {
+                    MediaReport::fields(a, b)
+                };
+                if fields.iter().any(FieldDiff::differs) {
+                    Some(Change::Changed(
+                        path.to_path_buf(),
+                        with_context(fields, context),
+                    ))
+                } else {
+                    None
+                }
+            }
+            (Some(_), None) => Some(match renames.get(path) {
+                Some(new_path) => Change::Renamed(path.to_path_buf(), new_path.to_path_buf()),
+                None => Change::Removed(path.to_path_buf()),
+            }),
+            (None, Some(_)) if renamed_to.contains(path) => None,
+            (None, Some(_)) => Some(Change::Added(path.to_path_buf())),
+            (None, None) => None,
+        })
+        .collect()
+}
+
+/// Match up the reports of two copies of a library by their content alone,
+/// their size and structural hash, wherever they are stored. Only content
+/// missing from either copy is returned, as removed or added under its path
+/// in that copy, in path order. A file stored more often in one copy than in
+/// the other is reported for every extra copy
+pub fn compare_content(
+    a_root: &Path,
+    a_reports: &[MediaReport],
+    b_root: &Path,
+    b_reports: &[MediaReport],
+) -> Vec<Change> {
+    let mut groups: HashMap<(u64, String), RenameCandidates> = HashMap::new();
+    for report in a_reports {
+        let key = (report.size, report.structural_hash());
+        groups
+            .entry(key)
+            .or_default()
+            .0
+            .push(report.relative_path(a_root));
+    }
+    for report in b_reports {
+        let key = (report.size, report.structural_hash());
+        groups
+            .entry(key)
+            .or_default()
+            .1
+            .push(report.relative_path(b_root));
+    }
+
+    let mut changes: Vec<Change> = groups
+        .into_values()
+        .flat_map(|(a, b)| {
+            // Copies that stayed at the same path pair up first
+            let mut a_only: Vec<&Path> =
+                a.iter().filter(|path| !b.contains(path)).copied().collect();
+            let mut b_only: Vec<&Path> =
+                b.iter().filter(|path| !a.contains(path)).copied().collect();
+            a_only.sort();
+            b_only.sort();
+
+            let paired = a_only.len().min(b_only.len());
+            let removed = a_only[paired..]
+                .iter()
+                .map(|path| Change::Removed(path.to_path_buf()));
+            let added = b_only[paired..]
+                .iter()
+                .map(|path| Change::Added(path.to_path_buf()));
+            removed.chain(added).collect::<Vec<_>>()
+        })
+        .collect();
+
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
+
+impl Change {
+    /// Path the change is listed under, the old one for renames
+    fn path(&self) -> &Path {
+        match self {
+            Change::Removed(path)
+            | Change::Added(path)
+            | Change::Changed(path, _)
+            | Change::Renamed(path, _) => path,
+        }
+    }
+}
+
+/// Pair up the files only in the first copy with those only in the second
+/// by their size and structural hash, from the old path to the new one.
+/// Files sharing a size and structure with another are left unpaired, as
+/// there is no telling which went where
+fn match_renames<'a>(pairs: &Pairs<'a>) -> HashMap<&'a Path, &'a Path> {
+    let mut candidates: HashMap<(u64, String), RenameCandidates> = HashMap::new();
+    for (&path, pair) in pairs {
+        match pair {
+            (Some(a), None) => {
+                let key = (a.size, a.structural_hash());
+                candidates.entry(key).or_default().0.push(path);
+            }
+            (None, Some(b)) => {
+                let key = (b.size, b.structural_hash());
+                candidates.entry(key).or_default().1.push(path);
+            }
+            _ => {}
+        }
+    }
+
+    candidates
+        .into_values()
+        .filter_map(|(old, new)| match (old.as_slice(), new.as_slice()) {
+            ([old], [new]) => Some((*old, *new)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Keep the changed fields and up to `context` unchanged fields before and
+/// after each of them, like the context lines of `diff -U`
+fn with_context(fields: Vec<FieldDiff>, context: usize) -> Vec<FieldDiff> {
+    let changed: Vec<usize> = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.differs())
+        .map(|(index, _)| index)
+        .collect();
+
+    fields
+        .into_iter()
+        .enumerate()
+        .filter(|(index, field)| {
+            field.differs()
+                || changed
+                    .iter()
+                    .any(|&changed| index.max(&changed) - index.min(&changed) <= context)
+        })
+        .map(|(_, field)| field)
+        .collect()
+}
+
+/// Render the changes as a unified listing, `-` marking the first copy and
+/// `+` the second. Unchanged context fields are indented without a marker
+pub fn render(changes: &[Change]) -> String {
+    let mut lines = Vec::new();
+
+    for change in changes {
+        match change {
+            Change::Removed(path) => lines.push(format!("- {}", path.to_string_lossy())),
+            Change::Added(path) => lines.push(format!("+ {}", path.to_string_lossy())),
+            Change::Renamed(old, new) => lines.push(format!(
+                "~ {} -> {}",
+                old.to_string_lossy(),
+                new.to_string_lossy()
+            )),
+            Change::Changed(path, diffs) => {
+                lines.push(path.to_string_lossy().into_owned());
+                for diff in diffs {
+                    if !diff.differs() {
+                        let value = diff.a.as_deref().unwrap_or(MISSING);
+                        lines.push(format!("\t  {}: {}", diff.field, value));
+                        continue;
+                    }
+                    if let Some(a) = &diff.a {
+                        lines.push(format!("\t- {}: {}", diff.field, a));
+                    }
+                    if let Some(b) = &diff.b {
+                        lines.push(format!("\t+ {}: {}", diff.field, b));
+                    }
+                }
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Render the changes with a column per copy, `field | a | b`, so the
+/// values of each changed field line up. With `color` the value of the first
+/// copy is highlighted red and the second green, context fields stay plain
+pub fn render_side_by_side(changes: &[Change], color: bool) -> String {
+    let mut lines = Vec::new();
+
+    for change in changes {
+        match change {
+            Change::Removed(path) => lines.push(format!("- {}", path.to_string_lossy())),
+            Change::Added(path) => lines.push(format!("+ {}", path.to_string_lossy())),
+            Change::Renamed(old, new) => lines.push(format!(
+                "~ {} -> {}",
+                old.to_string_lossy(),
+                new.to_string_lossy()
+            )),
+            Change::Changed(path, diffs) => {
+                lines.push(path.to_string_lossy().into_owned());
+
+                let rows: Vec<([&str; 3], bool)> = diffs
+                    .iter()
+                    .map(|diff| {
+                        let row = [
+                            diff.field.as_str(),
+                            diff.a.as_deref().unwrap_or(MISSING),
+                            diff.b.as_deref().unwrap_or(MISSING),
+                        ];
+                        (row, diff.differs())
+                    })
+                    .collect();
+                let width = |column: usize| {
+                    rows.iter()
+                        .map(|(row, _)| row[column].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                };
+                let (field_width, a_width) = (width(0), width(1));
+
+                for ([field, a, b], differs) in rows {
+                    let color = color && differs;
+                    lines.push(format!(
+                        "\t{:field_width$} | {} | {}",
+                        field,
+                        highlight(&format!("{:a_width$}", a, a_width = a_width), RED, color),
+                        highlight(b, GREEN, color),
+                        field_width = field_width,
+                    ));
+                }
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Tally the changes into the number of files changed, added, removed and
+/// renamed, followed by the `TOP_FIELDS` fields that changed in the most
+/// files. The same field of different streams counts once per file, and a
+/// stream appearing or disappearing counts as a field of its own
+pub fn summarize(changes: &[Change]) -> String {
+    let (mut added, mut removed, mut renamed, mut changed) = (0, 0, 0, 0);
+    let mut fields: BTreeMap<String, usize> = BTreeMap::new();
+
+    for change in changes {
+        match change {
+            Change::Removed(_) => removed += 1,
+            Change::Added(_) => added += 1,
+            Change::Renamed(_, _) => renamed += 1,
+            Change::Changed(_, diffs) => {
+                changed += 1;
+                let mut categories: Vec<String> = diffs
+                    .iter()
+                    .filter(|diff| diff.differs())
+                    .map(field_category)
+                    .collect();
+                categories.sort();
+                categories.dedup();
+                for category in categories {
+                    *fields.entry(category).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut lines = vec![format!(
+        "Summary: {} changed, {} added, {} removed, {} renamed",
+        changed, added, removed, renamed
+    )];
+
+    let mut fields: Vec<(String, usize)> = fields.into_iter().collect();
+    // Most files first, ties in field name order
+    fields.sort_by(|(_, a), (_, b)| b.cmp(a));
+    for (field, count) in fields.into_iter().take(TOP_FIELDS) {
+        let files = if count == 1 { "file" } else { "files" };
+        lines.push(format!("\t{}: {} {}", field, count, files));
+    }
+
+    lines.join("\n")
+}
+
+/// Field name without the stream index, `Stream #1 codec` becomes
+/// `Stream codec` and a whole stream `Stream added` or `Stream removed`
+fn field_category(diff: &FieldDiff) -> String {
+    let rest = match diff.field.strip_prefix("Stream #") {
+        Some(rest) => rest,
+        None => return diff.field.clone(),
+    };
+
+    match rest.split_once(' ') {
+        Some((_, field)) => format!("Stream {}", field),
+        None if diff.a.is_none() => String::from("Stream added"),
+        None => String::from("Stream removed"),
+    }
+}
+
+/// Wrap text in an ANSI color, when coloring is enabled
+fn highlight(text: &str, ansi_color: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", ansi_color, text, RESET)
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{StreamKind, StreamReport};
+    use std::time::Duration;
+
+    fn report(path: &str, codec: &str) -> MediaReport {
+        MediaReport {
+            path: PathBuf::from(path),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_secs(60)),
+            streams: vec![StreamReport {
+                kind: StreamKind::Video,
+                codec: String::from(codec),
+                ..StreamReport::default()
+            }],
+            ..MediaReport::default()
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = vec![
+            report("/a/same.mkv", "h264"),
+            report("/a/changed.mkv", "h264"),
+            report("/a/removed.mkv", "h264"),
+        ];
+        let b = vec![
+            report("/b/same.mkv", "h264"),
+            report("/b/changed.mkv", "hevc"),
+            report("/b/added.mkv", "h264"),
+        ];
+
+        let changes = compare(Path::new("/a"), &a, Path::new("/b"), &b, 0, false);
+        assert_eq!(
+            render(&changes),
+            "+ added.mkv\
+             \nchanged.mkv\n\t- Stream #0 codec: h264\n\t+ Stream #0 codec: hevc\
+             \n- removed.mkv"
+        );
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let a = report("/a/changed.mkv", "h264");
+        let mut b = report("/b/changed.mkv", "mpeg4");
+        b.format_name = String::from("avi");
+
+        let changes = compare(Path::new("/a"), &[a], Path::new("/b"), &[b], 0, false);
+        assert_eq!(
+            render_side_by_side(&changes, false),
+            "changed.mkv\
+             \n\tFormat          | matroska,webm | avi\
+             \n\tStream #0 codec | h264          | mpeg4"
+        );
+        assert!(render_side_by_side(&changes, true)
+            .ends_with("| \x1b[31mh264         \x1b[0m | \x1b[32mmpeg4\x1b[0m"));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let a = vec![
+            report("/a/same.mkv", "h264"),
+            report("/a/first.mkv", "h264"),
+            report("/a/second.mkv", "h264"),
+            report("/a/removed.mkv", "h264"),
+        ];
+        let mut b = vec![
+            report("/b/same.mkv", "h264"),
+            report("/b/first.mkv", "hevc"),
+            report("/b/second.mkv", "hevc"),
+        ];
+        b[1].bit_rate = 2_000_000;
+        let extra = b[2].streams[0].clone();
+        b[2].streams.push(extra);
+
+        let changes = compare(Path::new("/a"), &a, Path::new("/b"), &b, 2, false);
+        assert_eq!(
+            summarize(&changes),
+            "Summary: 2 changed, 0 added, 1 removed, 0 renamed\
+             \n\tStream codec: 2 files\
+             \n\tBit rate: 1 file\
+             \n\tStream added: 1 file"
+        );
+        assert_eq!(
+            summarize(&[]),
+            "Summary: 0 changed, 0 added, 0 removed, 0 renamed"
+        );
+    }
+
+    #[test]
+    fn test_renames() {
+        let mut a = vec![
+            report("/a/Show/S01E01.mkv", "h264"),
+            report("/a/twin-1.mkv", "h264"),
+            report("/a/twin-2.mkv", "h264"),
+        ];
+        let mut b = vec![
+            report("/b/Show/Season 1/S01E01.mkv", "h264"),
+            report("/b/twin-1 (copy).mkv", "h264"),
+            report("/b/twin-2 (copy).mkv", "h264"),
+        ];
+        a[0].size = 5000;
+        b[0].size = 5000;
+
+        let changes = compare(Path::new("/a"), &a, Path::new("/b"), &b, 0, true);
+        assert_eq!(
+            changes[0],
+            Change::Renamed(
+                PathBuf::from("Show/S01E01.mkv"),
+                PathBuf::from("Show/Season 1/S01E01.mkv")
+            )
+        );
+        // The copies share a size and structure, so neither counts as renamed
+        assert_eq!(
+            render(&changes[1..]),
+            "+ twin-1 (copy).mkv\n- twin-1.mkv\n+ twin-2 (copy).mkv\n- twin-2.mkv"
+        );
+    }
+
+    #[test]
+    fn test_compare_content() {
+        let mut a = vec![
+            report("/a/Movies/Heat.mkv", "h264"),
+            report("/a/dup-1.mkv", "h264"),
+            report("/a/dup-2.mkv", "h264"),
+            report("/a/gone.mkv", "h264"),
+        ];
+        let mut b = vec![
+            report("/b/Heat (1995)/Heat.mkv", "h264"),
+            report("/b/dup.mkv", "h264"),
+            report("/b/new.mkv", "h264"),
+        ];
+        a[0].size = 1;
+        b[0].size = 1;
+        a[1].size = 2;
+        a[2].size = 2;
+        b[1].size = 2;
+        a[3].size = 3;
+        b[2].size = 4;
+
+        let changes = compare_content(Path::new("/a"), &a, Path::new("/b"), &b);
+        assert_eq!(render(&changes), "- dup-2.mkv\n- gone.mkv\n+ new.mkv");
+
+        let same = compare_content(Path::new("/a"), &a, Path::new("/a"), &a);
+        assert_eq!(same, vec![]);
+    }
+
+    #[test]
+    fn test_context() {
+        let a = report("/a/changed.mkv", "h264");
+        let mut b = report("/b/changed.mkv", "h264");
+        b.size = 1024;
+
+        let changes = compare(Path::new("/a"), &[a], Path::new("/b"), &[b], 1, false);
+        assert_eq!(
+            render(&changes),
+            "changed.mkv\n\t  Bit rate: 0 B/s\n\t- Size: 0\n\t+ Size: 1024\n\t  Stream #0 kind: Video"
+        );
+        assert_eq!(
+            render_side_by_side(&changes, true).lines().nth(1),
+            Some("\tBit rate       | 0 B/s | 0 B/s")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::report::MediaReport;
+
+/// A column of `--format csv`, selected and ordered with `--columns`
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Path,
+    Format,
+    /// Seconds, empty when unknown
+    Duration,
+    Bitrate,
+    Size,
+    /// Codec of the video stream representing the file
+    Vcodec,
+    /// Codec of the audio stream representing the file
+    Acodec,
+    Width,
+    Height,
+    Streams,
+    Editions,
+    Muxer,
+    ProbeScore,
+    Efficiency,
+    /// Empty without `--categorize`
+    Category,
+    /// Marker labels separated by `;`
+    Markers,
+    Error,
+}
+
+impl Column {
+    /// Every column, in the order written without `--columns`
+    pub const ALL: [Column; 17] = [
+        Column::Path,
+        Column::Format,
+        Column::Duration,
+        Column::Bitrate,
+        Column::Size,
+        Column::Vcodec,
+        Column::Acodec,
+        Column::Width,
+        Column::Height,
+        Column::Streams,
+        Column::Editions,
+        Column::Muxer,
+        Column::ProbeScore,
+        Column::Efficiency,
+        Column::Category,
+        Column::Markers,
+        Column::Error,
+    ];
+
+    /// Name in the header row, the same as accepted by `--columns`
+    fn name(&self) -> &'static str {
+        match self {
+            Column::Path => "path",
+            Column::Format => "format",
+            Column::Duration => "duration",
+            Column::Bitrate => "bitrate",
+            Column::Size => "size",
+            Column::Vcodec => "vcodec",
+            Column::Acodec => "acodec",
+            Column::Width => "width",
+            Column::Height => "height",
+            Column::Streams => "streams",
+            Column::Editions => "editions",
+            Column::Muxer => "muxer",
+            Column::ProbeScore => "probe-score",
+            Column::Efficiency => "efficiency",
+            Column::Category => "category",
+            Column::Markers => "markers",
+            Column::Error => "error",
+        }
+    }
+
+    fn value(&self, report: &MediaReport) -> String {
+        let resolution = report
+            .best_video
+            .and_then(|index| report.streams.get(index))
+            .and_then(|stream| stream.resolution);
+
+        match self {
+            Column::Path => report.path.to_string_lossy().into_owned(),
+            Column::Format => report.format_name.clone(),
+            Column::Duration => optional(report.duration.map(|duration| duration.as_secs_f64())),
+            Column::Bitrate => report.bit_rate.to_string(),
+            Column::Size => report.size.to_string(),
+            Column::Vcodec => optional(report.video_codec()),
+            Column::Acodec => optional(report.audio_codec()),
+            Column::Width => optional(resolution.map(|(width, _)| width)),
+            Column::Height => optional(resolution.map(|(_, height)| height)),
+            Column::Streams => report.streams.len().to_string(),
+            Column::Editions => report.editions.to_string(),
+            Column::Muxer => optional(report.muxer.as_ref()),
+            Column::ProbeScore => optional(report.probe_score),
+            Column::Efficiency => optional(report.efficiency),
+            Column::Category => optional(report.category.map(|category| category.label())),
+            Column::Markers => {
+                let labels: Vec<&str> = report.markers.iter().map(|marker| marker.label).collect();
+                labels.join(";")
+            }
+            Column::Error => optional(report.error.as_ref()),
+        }
+    }
+}
+
+/// Header row naming the columns
+pub fn header(columns: &[Column]) -> String {
+    let names: Vec<&str> = columns.iter().map(Column::name).collect();
+    names.join(",")
+}
+
+/// Render a report as a single CSV row of the given columns
+pub fn render(report: &MediaReport, columns: &[Column]) -> String {
+    let values: Vec<String> = columns
+        .iter()
+        .map(|column| escape(&column.value(report)))
+        .collect();
+    values.join(",")
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Quote a value holding a separator, quote or line break, doubling quotes
+fn escape(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{StreamKind, StreamReport};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn report() -> MediaReport {
+        MediaReport {
+            path: PathBuf::from("movies/Heat, Director's Cut.mkv"),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_millis(5400500)),
+            bit_rate: 4_000_000,
+            size: 2_700_250_000,
+            streams: vec![StreamReport {
+                kind: StreamKind::Video,
+                codec: String::from("hevc"),
+                resolution: Some((1920, 1080)),
+                ..StreamReport::default()
+            }],
+            best_video: Some(0),
+            ..MediaReport::default()
+        }
+    }
+
+    /// Split a row back into its values, undoing `escape`
+    fn fields(row: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = row.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn test_selected_columns() {
+        let columns = [
+            Column::Path,
+            Column::Duration,
+            Column::Vcodec,
+            Column::Acodec,
+            Column::Height,
+        ];
+        assert_eq!(header(&columns), "path,duration,vcodec,acodec,height");
+        assert_eq!(
+            render(&report(), &columns),
+            "\"movies/Heat, Director's Cut.mkv\",5400.5,hevc,,1080"
+        );
+    }
+
+    #[test]
+    fn test_all_columns() {
+        assert_eq!(
+            header(&Column::ALL),
+            "path,format,duration,bitrate,size,vcodec,acodec,width,height,streams,editions,\
+             muxer,probe-score,efficiency,category,markers,error"
+        );
+        let row = fields(&render(&report(), &Column::ALL));
+        assert_eq!(row.len(), Column::ALL.len());
+        assert_eq!(
+            row[..3],
+            ["movies/Heat, Director's Cut.mkv", "matroska,webm", "5400.5"]
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
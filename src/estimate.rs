@@ -0,0 +1,168 @@
+use crate::locale::NumberFormat;
+use crate::report::{format_bit_rate, format_duration, format_size};
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Bytes ffmpeg reads while probing a file with the default `probesize`
+const PROBE_BYTES: u64 = 5_000_000;
+/// Bytes read from a single file by the throughput benchmark
+const BENCHMARK_BYTES_PER_FILE: u64 = 8 * 1024 * 1024;
+/// Bytes read in total by the throughput benchmark
+const BENCHMARK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Prediction of the cost of a full scan, made by `--estimate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub files: usize,
+    pub total_bytes: u64,
+    /// Bytes the analysis is expected to read in the selected mode
+    pub read_bytes: u64,
+    /// Measured read throughput of the storage in bytes per second
+    pub throughput: Option<f64>,
+    /// The selected mode reads the whole file instead of probing it
+    pub full_read: bool,
+}
+
+impl Estimate {
+    /// Predict the scan of the given files and sizes, benchmarking the
+    /// storage by reading from the largest files
+    pub fn new(files: &[(PathBuf, u64)], full_read: bool) -> Estimate {
+        let sizes: Vec<u64> = files.iter().map(|(_, size)| *size).collect();
+
+        let mut largest: Vec<&(PathBuf, u64)> = files.iter().collect();
+        largest.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+        Estimate {
+            files: files.len(),
+            total_bytes: sizes.iter().sum(),
+            read_bytes: read_bytes(&sizes, full_read),
+            throughput: measure_throughput(largest.into_iter().map(|(path, _)| path)),
+            full_read,
+        }
+    }
+
+    /// Expected duration of the scan, `None` when the throughput is unknown
+    pub fn eta(&self) -> Option<Duration> {
+        self.throughput
+            .filter(|throughput| *throughput > 0.0)
+            .map(|throughput| Duration::from_secs_f64(self.read_bytes as f64 / throughput))
+    }
+
+    /// Render the estimate with the numbers written the way `numbers` describes
+    pub fn render(&self, numbers: &NumberFormat) -> String {
+        format!(
+            "Files: {}\nTotal size: {}\nMode: {}\nBytes to read: {}\nThroughput: {}\nEstimated time: {}",
+            numbers.localize(&self.files.to_string()),
+            numbers.localize(&format_size(self.total_bytes)),
+            if self.full_read { "full read" } else { "probe" },
+            numbers.localize(&format_size(self.read_bytes)),
+            self.throughput
+                .map(|throughput| numbers.localize(&format_bit_rate(throughput as i64)))
+                .unwrap_or_else(|| String::from("unknown")),
+            self.eta()
+                .map(|eta| numbers.localize(&format_duration(&eta)))
+                .unwrap_or_else(|| String::from("unknown")),
+        )
+    }
+}
+
+/// Bytes read to analyze files of the given sizes. Probing reads at most
+/// the probe size of each file, hashing and the packet size and interleave
+/// checks read it all
+fn read_bytes(sizes: &[u64], full_read: bool) -> u64 {
+    sizes
+        .iter()
+        .map(|&size| {
+            if full_read {
+                size
+            } else {
+                size.min(PROBE_BYTES)
+            }
+        })
+        .sum()
+}
+
+/// Read the start of the given files until the benchmark budget is used up
+/// and return the achieved throughput in bytes per second. Files that were
+/// read recently may be served from the page cache, inflating the result
+fn measure_throughput<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> Option<f64> {
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut read = 0;
+    let start = Instant::now();
+
+    for path in paths {
+        if read >= BENCHMARK_BYTES {
+            break;
+        }
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let mut chunk = file.take(BENCHMARK_BYTES_PER_FILE.min(BENCHMARK_BYTES - read));
+        while let Ok(count) = chunk.read(&mut buffer) {
+            if count == 0 {
+                break;
+            }
+            read += count as u64;
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    if read == 0 || elapsed <= 0.0 {
+        None
+    } else {
+        Some(read as f64 / elapsed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_bytes() {
+        let sizes = [1_000, 20_000_000, 4_000_000_000];
+        assert_eq!(read_bytes(&sizes, false), 1_000 + 2 * PROBE_BYTES);
+        assert_eq!(read_bytes(&sizes, true), 4_020_001_000);
+    }
+
+    #[test]
+    fn test_eta() {
+        let estimate = Estimate {
+            files: 2,
+            total_bytes: 2_000_000_000,
+            read_bytes: 10_000_000,
+            throughput: Some(100_000.0),
+            full_read: false,
+        };
+        assert_eq!(estimate.eta(), Some(Duration::from_secs(100)));
+        assert!(estimate
+            .render(&NumberFormat::C)
+            .ends_with("\nEstimated time: 01:40"));
+
+        let unknown = Estimate {
+            throughput: None,
+            ..estimate
+        };
+        assert_eq!(unknown.eta(), None);
+    }
+
+    #[test]
+    fn test_render_locale() {
+        let estimate = Estimate {
+            files: 1250,
+            total_bytes: 2_000_000_000,
+            read_bytes: 6_250_000_000,
+            throughput: Some(125_000_000.0),
+            full_read: true,
+        };
+        assert_eq!(
+            estimate.render(&NumberFormat::for_locale("de_DE")),
+            "Files: 1.250\nTotal size: 2,00 GB\nMode: full read\nBytes to read: 6,25 GB\n\
+             Throughput: 125,00 MB/s\nEstimated time: 00:50"
+        );
+    }
+}
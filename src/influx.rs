@@ -0,0 +1,155 @@
+use crate::report::MediaReport;
+
+/// Measurement name every file is written under
+const MEASUREMENT: &str = "media_file";
+
+/// Render a report as a single InfluxDB line protocol point, tagged by the
+/// path, primary codec and container, timestamped in nanoseconds since the
+/// epoch. Every file of a scan shares the timestamp, so the path tag is what
+/// keeps their points in distinct series instead of overwriting each other
+pub fn render(report: &MediaReport, timestamp: u128) -> String {
+    let mut line = String::from(MEASUREMENT);
+
+    let path = report.path.to_string_lossy();
+    let tags = [
+        ("path", Some(path.as_ref())),
+        ("codec", primary_codec(report)),
+        ("container", Some(report.format_name.as_str())),
+    ];
+    for (key, value) in tags {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            line.push_str(&format!(",{}={}", key, escape_tag(value)));
+        }
+    }
+
+    let mut fields = Vec::new();
+    // Leave the field out entirely rather than writing a fake zero
+    if let Some(duration) = report.duration {
+        fields.push(format!("duration={}", duration.as_secs_f64()));
+    }
+    if let Some(error) = &report.error {
+        fields.push(format!("error=\"{}\"", escape_string_field(error)));
+    }
+    fields.push(format!("bitrate={}i", report.bit_rate));
+    fields.push(format!("size={}i", report.size));
+
+    line.push_str(&format!(" {} {}", fields.join(","), timestamp));
+    line
+}
+
+/// Codec of the representative video stream, falling back to the audio
+/// stream for audio only files
+fn primary_codec(report: &MediaReport) -> Option<&str> {
+    report.video_codec().or_else(|| report.audio_codec())
+}
+
+/// Escape a tag value, where commas, equals signs and spaces are significant
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ',' | '=' | ' ' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape the contents of a double quoted string field value
+fn escape_string_field(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{StreamKind, StreamReport};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn stream(kind: StreamKind, codec: &str) -> StreamReport {
+        StreamReport {
+            kind,
+            codec: String::from(codec),
+            ..StreamReport::default()
+        }
+    }
+
+    fn report(path: &str, streams: Vec<StreamReport>) -> MediaReport {
+        let first = |kind| streams.iter().position(|stream| stream.kind == kind);
+        let (best_video, best_audio) = (first(StreamKind::Video), first(StreamKind::Audio));
+        MediaReport {
+            path: PathBuf::from(path),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_millis(5400500)),
+            bit_rate: 4_000_000,
+            size: 2_700_250_000,
+            streams,
+            best_video,
+            best_audio,
+            ..MediaReport::default()
+        }
+    }
+
+    #[test]
+    fn test_video_point() {
+        let report = report(
+            "tv/Show/S01E01.mkv",
+            vec![
+                stream(StreamKind::Audio, "aac"),
+                stream(StreamKind::Video, "h264"),
+            ],
+        );
+
+        assert_eq!(
+            render(&report, 1650000000000000000),
+            "media_file,path=tv/Show/S01E01.mkv,codec=h264,container=matroska\\,webm \
+             duration=5400.5,bitrate=4000000i,size=2700250000i 1650000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_audio_only_point() {
+        let audio_only = report("a.mka", vec![stream(StreamKind::Audio, "flac")]);
+        assert!(render(&audio_only, 0).starts_with("media_file,path=a.mka,codec=flac,container="));
+
+        let mut no_streams = report("a.mka", vec![]);
+        no_streams.duration = None;
+        assert!(!render(&no_streams, 0).contains("duration="));
+        assert!(render(&no_streams, 0).starts_with("media_file,path=a.mka,container="));
+    }
+
+    #[test]
+    fn test_distinct_series() {
+        let streams = || vec![stream(StreamKind::Video, "h264")];
+        let series = |line: &str| line.split(' ').next().unwrap().to_owned();
+
+        let first = render(&report("tv/Show/S01E01.mkv", streams()), 0);
+        let second = render(&report("tv/Show/S01E02.mkv", streams()), 0);
+        assert_ne!(series(&first), series(&second));
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(escape_tag("a b,c=d"), "a\\ b\\,c\\=d");
+        assert_eq!(
+            escape_string_field("The \"Best\" of C:\\Media"),
+            "The \\\"Best\\\" of C:\\\\Media"
+        );
+    }
+}
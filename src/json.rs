@@ -0,0 +1,389 @@
+use crate::report::{
+    format_ratio, FrameSizes, Interleave, KeyframeCadence, MediaReport, StreamReport,
+};
+use serde_json::{json, Map, Value};
+
+/// Render a report as a single line JSON object, with the streams nested
+/// in a `streams` array
+pub fn render(report: &MediaReport) -> String {
+    let mut object = file_fields(report);
+    object.insert(
+        String::from("streams"),
+        report.streams.iter().map(stream_object).collect(),
+    );
+    object.insert(
+        String::from("color"),
+        report.color.as_ref().map_or(Value::Null, |color| {
+            json!({
+                "bits_per_raw_sample": color.bits_per_raw_sample,
+                "mastering_display": color.mastering_display,
+                "content_light_level": color.content_light_level,
+            })
+        }),
+    );
+    object.insert(
+        String::from("first_keyframe"),
+        first_keyframe(report).map_or(
+            Value::Null,
+            |(offset, clean_start)| json!({ "offset": offset, "clean_start": clean_start }),
+        ),
+    );
+    object.insert(String::from("captions"), caption_labels(report).into());
+    object.insert(
+        String::from("keyframe_cadence"),
+        cadence(report).map_or(Value::Null, |cadence| {
+            json!({
+                "interval": cadence.interval,
+                "min": cadence.min,
+                "max": cadence.max,
+                "regular": cadence.regular,
+            })
+        }),
+    );
+    object.insert(
+        String::from("frame_sizes"),
+        frame_sizes(report).map_or(Value::Null, |sizes| {
+            json!({
+                "min": sizes.min,
+                "max": sizes.max,
+                "average": sizes.average,
+            })
+        }),
+    );
+    object.insert(
+        String::from("interleave"),
+        interleave(report).map_or(Value::Null, |interleave| {
+            json!({
+                "max_gap": interleave.max_gap,
+                "good": interleave.good,
+            })
+        }),
+    );
+    object.insert(
+        String::from("frame_count"),
+        report
+            .frame_count
+            .as_ref()
+            .map_or(Value::Null, |frame_count| {
+                json!({
+                    "declared": frame_count.declared,
+                    "estimated": frame_count.estimated,
+                })
+            }),
+    );
+    object.insert(
+        String::from("sample_aspect"),
+        report.sample_aspect.map_or(Value::Null, |aspect| {
+            json!({
+                "container": format_ratio(aspect.container),
+                "codec": format_ratio(aspect.codec),
+            })
+        }),
+    );
+    object.insert(
+        String::from("markers"),
+        report
+            .markers
+            .iter()
+            .map(|marker| json!({ "label": marker.label, "reason": marker.reason }))
+            .collect(),
+    );
+
+    Value::Object(object).to_string()
+}
+
+/// Render a report as one single line JSON object per stream, for consumers
+/// that can't handle nesting. A file without streams still gets one row,
+/// with every `stream_` key set to `null`. The keys of every row are:
+///
+/// - file: `path`, `format`, `muxer`, `probe_score`, `duration` (seconds),
+///   `duration_sampled`, `bit_rate`, `size`, `editions`, `efficiency`,
+///   `start_spread` (seconds), `category`, `error`
+/// - `format_flags`: comma separated flags, `null` without `--format-flags`
+/// - `markers`: comma separated marker labels
+/// - `color_bits_per_raw_sample`, `color_mastering_display`,
+///   `color_content_light_level`
+/// - `first_keyframe_offset`, `first_keyframe_clean_start`
+/// - `captions`: comma separated caption services
+/// - `keyframe_interval`, `keyframes_regular`
+/// - `frame_size_min`, `frame_size_max`, `frame_size_average` (bytes)
+/// - `interleave_max_gap` (seconds), `interleave_good`
+/// - `frame_count_declared`, `frame_count_estimated`
+/// - `sample_aspect_container`, `sample_aspect_codec`: as `num:den`
+/// - stream: `stream_index`, `stream_kind`, `stream_codec`,
+///   `stream_codec_long_name`, `stream_width`, `stream_height`,
+///   `stream_language`, `stream_title`, `stream_attached_picture`
+pub fn render_flattened(report: &MediaReport) -> Vec<String> {
+    let mut file = file_fields(report);
+    let labels: Vec<&str> = report.markers.iter().map(|marker| marker.label).collect();
+    file.insert(String::from("markers"), Value::from(labels.join(",")));
+    file.insert(
+        String::from("format_flags"),
+        json!(report.format_flags.as_ref().map(|flags| flags.join(","))),
+    );
+
+    let color = report.color.as_ref();
+    let keyframe = first_keyframe(report);
+    let frame_count = report.frame_count.as_ref();
+    let aspect = report.sample_aspect.as_ref();
+    let flattened = [
+        (
+            "color_bits_per_raw_sample",
+            json!(color.and_then(|color| color.bits_per_raw_sample)),
+        ),
+        (
+            "color_mastering_display",
+            json!(color.and_then(|color| color.mastering_display.as_ref())),
+        ),
+        (
+            "color_content_light_level",
+            json!(color.and_then(|color| color.content_light_level.as_ref())),
+        ),
+        (
+            "first_keyframe_offset",
+            json!(keyframe.map(|(offset, _)| offset)),
+        ),
+        (
+            "first_keyframe_clean_start",
+            json!(keyframe.map(|(_, clean_start)| clean_start)),
+        ),
+        ("captions", json!(caption_labels(report).join(","))),
+        (
+            "keyframe_interval",
+            json!(cadence(report).map(|cadence| cadence.interval)),
+        ),
+        (
+            "keyframes_regular",
+            json!(cadence(report).map(|cadence| cadence.regular)),
+        ),
+        (
+            "frame_size_min",
+            json!(frame_sizes(report).map(|sizes| sizes.min)),
+        ),
+        (
+            "frame_size_max",
+            json!(frame_sizes(report).map(|sizes| sizes.max)),
+        ),
+        (
+            "frame_size_average",
+            json!(frame_sizes(report).map(|sizes| sizes.average)),
+        ),
+        (
+            "interleave_max_gap",
+            json!(interleave(report).map(|interleave| interleave.max_gap)),
+        ),
+        (
+            "interleave_good",
+            json!(interleave(report).map(|interleave| interleave.good)),
+        ),
+        (
+            "frame_count_declared",
+            json!(frame_count.map(|frame_count| frame_count.declared)),
+        ),
+        (
+            "frame_count_estimated",
+            json!(frame_count.map(|frame_count| frame_count.estimated)),
+        ),
+        (
+            "sample_aspect_container",
+            json!(aspect.map(|aspect| format_ratio(aspect.container))),
+        ),
+        (
+            "sample_aspect_codec",
+            json!(aspect.map(|aspect| format_ratio(aspect.codec))),
+        ),
+    ];
+    for (key, value) in flattened {
+        file.insert(String::from(key), value);
+    }
+
+    let streams: Vec<Option<(usize, &StreamReport)>> = if report.streams.is_empty() {
+        vec![None]
+    } else {
+        report.streams.iter().enumerate().map(Some).collect()
+    };
+
+    streams
+        .into_iter()
+        .map(|stream| {
+            let mut row = file.clone();
+            let fields = stream.map(|(index, stream)| (index, stream_object(stream)));
+            row.insert(
+                String::from("stream_index"),
+                json!(fields.as_ref().map(|(index, _)| index)),
+            );
+            for key in STREAM_KEYS {
+                let value = fields
+                    .as_ref()
+                    .and_then(|(_, object)| object.get(key).cloned())
+                    .unwrap_or(Value::Null);
+                row.insert(format!("stream_{}", key), value);
+            }
+            Value::Object(row).to_string()
+        })
+        .collect()
+}
+
+/// Keys of a stream object, in the order they are flattened in
+const STREAM_KEYS: [&str; 8] = [
+    "kind",
+    "codec",
+    "codec_long_name",
+    "width",
+    "height",
+    "language",
+    "title",
+    "attached_picture",
+];
+
+/// Scalar fields of the file shared by both shapes
+fn file_fields(report: &MediaReport) -> Map<String, Value> {
+    let fields = json!({
+        "path": report.path.to_string_lossy(),
+        "format": report.format_name,
+        "format_flags": report.format_flags,
+        "muxer": report.muxer,
+        "probe_score": report.probe_score,
+        "duration": report.duration.map(|duration| duration.as_secs_f64()),
+        "duration_sampled": report.duration_sampled,
+        "bit_rate": report.bit_rate,
+        "size": report.size,
+        "editions": report.editions,
+        "efficiency": report.efficiency,
+        "start_spread": report.start_spread,
+        "category": report.category.map(|category| category.label()),
+        "error": report.error,
+    });
+
+    match fields {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+fn stream_object(stream: &StreamReport) -> Value {
+    json!({
+        "kind": stream.kind.label().to_lowercase(),
+        "codec": stream.codec,
+        "codec_long_name": stream.codec_long_name,
+        "width": stream.resolution.map(|(width, _)| width),
+        "height": stream.resolution.map(|(_, height)| height),
+        "language": stream.language,
+        "title": stream.title,
+        "attached_picture": stream.attached_picture,
+    })
+}
+
+fn first_keyframe(report: &MediaReport) -> Option<(f64, bool)> {
+    report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.first_keyframe.as_ref())
+        .map(|keyframe| (keyframe.offset, keyframe.clean_start))
+}
+
+fn cadence(report: &MediaReport) -> Option<&KeyframeCadence> {
+    report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.cadence.as_ref())
+}
+
+fn frame_sizes(report: &MediaReport) -> Option<&FrameSizes> {
+    report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.frame_sizes.as_ref())
+}
+
+fn interleave(report: &MediaReport) -> Option<&Interleave> {
+    report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.interleave.as_ref())
+}
+
+fn caption_labels(report: &MediaReport) -> Vec<&'static str> {
+    report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.captions.as_ref())
+        .map(|captions| captions.labels())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::StreamKind;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn stream(kind: StreamKind, codec: &str) -> StreamReport {
+        StreamReport {
+            kind,
+            codec: String::from(codec),
+            ..StreamReport::default()
+        }
+    }
+
+    fn report(streams: Vec<StreamReport>) -> MediaReport {
+        MediaReport {
+            path: PathBuf::from("tv/Show/S01E01.mkv"),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_millis(5400500)),
+            bit_rate: 4_000_000,
+            size: 2_700_250_000,
+            streams,
+            ..MediaReport::default()
+        }
+    }
+
+    #[test]
+    fn test_nested() {
+        let mut video = stream(StreamKind::Video, "h264");
+        video.resolution = Some((1920, 1080));
+        let rendered: Value = serde_json::from_str(&render(&report(vec![video]))).unwrap();
+
+        assert_eq!(rendered["path"], "tv/Show/S01E01.mkv");
+        assert_eq!(rendered["duration"], 5400.5);
+        assert_eq!(rendered["streams"][0]["codec"], "h264");
+        assert_eq!(rendered["streams"][0]["width"], 1920);
+        assert_eq!(rendered["markers"], json!([]));
+    }
+
+    #[test]
+    fn test_flattened() {
+        let rows = render_flattened(&report(vec![
+            stream(StreamKind::Video, "h264"),
+            stream(StreamKind::Audio, "aac"),
+        ]));
+        assert_eq!(rows.len(), 2);
+
+        let audio: Value = serde_json::from_str(&rows[1]).unwrap();
+        assert_eq!(audio["path"], "tv/Show/S01E01.mkv");
+        assert_eq!(audio["stream_index"], 1);
+        assert_eq!(audio["stream_kind"], "audio");
+        assert_eq!(audio["stream_codec"], "aac");
+        assert!(audio.get("streams").is_none());
+    }
+
+    #[test]
+    fn test_flattened_format_flags() {
+        let mut report = report(vec![]);
+        report.format_flags = Some(vec!["seekable", "live"]);
+        let row: Value = serde_json::from_str(&render_flattened(&report)[0]).unwrap();
+
+        assert_eq!(row["format_flags"], "seekable,live");
+    }
+
+    #[test]
+    fn test_flattened_without_streams() {
+        let rows = render_flattened(&report(vec![]));
+        let row: Value = serde_json::from_str(&rows[0]).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(row["stream_index"], Value::Null);
+        assert_eq!(row["stream_codec"], Value::Null);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::report::{format_ratio, MediaReport, StreamReport};
+use ::kdl::{KdlDocument, KdlEntry, KdlNode};
+
+/// Render a report as a KDL `file` node, with the path as its argument,
+/// the scalar fields as properties and a child node per stream
+pub fn render(report: &MediaReport) -> String {
+    let mut node = KdlNode::new("file");
+    node.push(report.path.to_string_lossy().into_owned());
+    node.push(KdlEntry::new_prop("format", report.format_name.as_str()));
+    if let Some(muxer) = &report.muxer {
+        node.push(KdlEntry::new_prop("muxer", muxer.as_str()));
+    }
+    if let Some(score) = report.probe_score {
+        node.push(KdlEntry::new_prop("probe-score", i64::from(score)));
+    }
+    if let Some(duration) = report.duration {
+        node.push(KdlEntry::new_prop("duration", duration.as_secs_f64()));
+    }
+    if report.duration_sampled {
+        node.push(KdlEntry::new_prop("duration-sampled", true));
+    }
+    node.push(KdlEntry::new_prop("bitrate", report.bit_rate));
+    node.push(KdlEntry::new_prop("size", integer(report.size)));
+    if report.editions > 1 {
+        node.push(KdlEntry::new_prop("editions", integer(report.editions)));
+    }
+    if let Some(efficiency) = report.efficiency {
+        node.push(KdlEntry::new_prop("efficiency", efficiency));
+    }
+    if let Some(spread) = report.start_spread {
+        node.push(KdlEntry::new_prop("start-spread", spread));
+    }
+    if let Some(category) = report.category {
+        node.push(KdlEntry::new_prop("category", category.label()));
+    }
+    if let Some(error) = &report.error {
+        node.push(KdlEntry::new_prop("error", error.as_str()));
+    }
+
+    let children = node.ensure_children();
+    if let Some(flags) = &report.format_flags {
+        let mut flags_node = KdlNode::new("format-flags");
+        for flag in flags {
+            flags_node.push(*flag);
+        }
+        children.nodes_mut().push(flags_node);
+    }
+    for stream in &report.streams {
+        children.nodes_mut().push(stream_node(stream));
+    }
+
+    if let Some(color) = &report.color {
+        let mut color_node = KdlNode::new("color");
+        if let Some(bits) = color.bits_per_raw_sample {
+            color_node.push(KdlEntry::new_prop("bits-per-raw-sample", i64::from(bits)));
+        }
+        if let Some(mastering) = &color.mastering_display {
+            color_node.push(KdlEntry::new_prop("mastering-display", mastering.as_str()));
+        }
+        if let Some(light_level) = &color.content_light_level {
+            color_node.push(KdlEntry::new_prop(
+                "content-light-level",
+                light_level.as_str(),
+            ));
+        }
+        children.nodes_mut().push(color_node);
+    }
+
+    if let Some(keyframe) = report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.first_keyframe.as_ref())
+    {
+        let mut keyframe_node = KdlNode::new("first-keyframe");
+        keyframe_node.push(KdlEntry::new_prop("offset", keyframe.offset));
+        keyframe_node.push(KdlEntry::new_prop("clean-start", keyframe.clean_start));
+        children.nodes_mut().push(keyframe_node);
+    }
+
+    if let Some(cadence) = report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.cadence.as_ref())
+    {
+        let mut cadence_node = KdlNode::new("keyframe-cadence");
+        cadence_node.push(KdlEntry::new_prop("interval", cadence.interval));
+        cadence_node.push(KdlEntry::new_prop("min", cadence.min));
+        cadence_node.push(KdlEntry::new_prop("max", cadence.max));
+        cadence_node.push(KdlEntry::new_prop("regular", cadence.regular));
+        children.nodes_mut().push(cadence_node);
+    }
+
+    if let Some(sizes) = report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.frame_sizes.as_ref())
+    {
+        let mut sizes_node = KdlNode::new("frame-sizes");
+        sizes_node.push(KdlEntry::new_prop("min", integer(sizes.min)));
+        sizes_node.push(KdlEntry::new_prop("max", integer(sizes.max)));
+        sizes_node.push(KdlEntry::new_prop("average", integer(sizes.average)));
+        children.nodes_mut().push(sizes_node);
+    }
+
+    if let Some(interleave) = report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.interleave.as_ref())
+    {
+        let mut interleave_node = KdlNode::new("interleave");
+        interleave_node.push(KdlEntry::new_prop("max-gap", interleave.max_gap));
+        interleave_node.push(KdlEntry::new_prop("good", interleave.good));
+        children.nodes_mut().push(interleave_node);
+    }
+
+    if let Some(captions) = report
+        .packets
+        .as_ref()
+        .and_then(|packets| packets.captions.as_ref())
+    {
+        let mut captions_node = KdlNode::new("captions");
+        for label in captions.labels() {
+            captions_node.push(label);
+        }
+        children.nodes_mut().push(captions_node);
+    }
+
+    if let Some(frame_count) = &report.frame_count {
+        let mut frame_count_node = KdlNode::new("frame-count");
+        frame_count_node.push(KdlEntry::new_prop(
+            "declared",
+            integer(frame_count.declared),
+        ));
+        frame_count_node.push(KdlEntry::new_prop(
+            "estimated",
+            integer(frame_count.estimated),
+        ));
+        children.nodes_mut().push(frame_count_node);
+    }
+
+    if let Some(aspect) = &report.sample_aspect {
+        let mut aspect_node = KdlNode::new("sample-aspect");
+        aspect_node.push(KdlEntry::new_prop(
+            "container",
+            format_ratio(aspect.container),
+        ));
+        aspect_node.push(KdlEntry::new_prop("codec", format_ratio(aspect.codec)));
+        children.nodes_mut().push(aspect_node);
+    }
+
+    for marker in &report.markers {
+        let mut marker_node = KdlNode::new("marker");
+        marker_node.push(marker.label);
+        children.nodes_mut().push(marker_node);
+    }
+
+    if children.nodes().is_empty() {
+        node.clear_children();
+    }
+
+    let mut document = KdlDocument::new();
+    document.nodes_mut().push(node);
+    document.fmt();
+    document.to_string().trim_end().to_owned()
+}
+
+/// Child node describing a single stream
+fn stream_node(stream: &StreamReport) -> KdlNode {
+    let mut node = KdlNode::new("stream");
+    node.push(KdlEntry::new_prop(
+        "kind",
+        stream.kind.label().to_lowercase(),
+    ));
+    node.push(KdlEntry::new_prop("codec", stream.codec.as_str()));
+    if let Some(long_name) = &stream.codec_long_name {
+        node.push(KdlEntry::new_prop("codec-long-name", long_name.as_str()));
+    }
+    if let Some((width, height)) = stream.resolution {
+        node.push(KdlEntry::new_prop("width", i64::from(width)));
+        node.push(KdlEntry::new_prop("height", i64::from(height)));
+    }
+    if let Some(language) = &stream.language {
+        node.push(KdlEntry::new_prop("language", language.as_str()));
+    }
+    if let Some(title) = &stream.title {
+        node.push(KdlEntry::new_prop("title", title.as_str()));
+    }
+    if stream.attached_picture {
+        node.push(KdlEntry::new_prop("attached-picture", true));
+    }
+    node
+}
+
+/// KDL integers are signed, clamp the (never realistic) overflow
+fn integer<T: TryInto<i64>>(value: T) -> i64 {
+    value.try_into().unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::StreamKind;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn report(path: &str, streams: Vec<StreamReport>) -> MediaReport {
+        MediaReport {
+            path: PathBuf::from(path),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_millis(5400500)),
+            bit_rate: 4_000_000,
+            size: 2_700_250_000,
+            streams,
+            ..MediaReport::default()
+        }
+    }
+
+    #[test]
+    fn test_file_node() {
+        let report = report(
+            "tv/Show/S01E01.mkv",
+            vec![StreamReport {
+                kind: StreamKind::Video,
+                codec: String::from("h264"),
+                resolution: Some((1920, 1080)),
+                ..StreamReport::default()
+            }],
+        );
+
+        assert_eq!(
+            render(&report),
+            "file \"tv/Show/S01E01.mkv\" format=\"matroska,webm\" duration=5400.5 \
+             bitrate=4000000 size=2700250000 {\
+             \n    stream kind=\"video\" codec=\"h264\" width=1920 height=1080\
+             \n}"
+        );
+    }
+
+    #[test]
+    fn test_quoting() {
+        let report = report("movies/\"Heat\" {1995}\\cut.mkv", vec![]);
+        let rendered = render(&report);
+        assert!(rendered.starts_with("file \"movies/\\\"Heat\\\" {1995}\\\\cut.mkv\" "));
+        assert!(rendered.ends_with(" size=2700250000"));
+    }
+}
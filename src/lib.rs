@@ -0,0 +1,3080 @@
+//! Reports on the media files of a folder, meant to be diffed between copies
+//! of a library. The binary is the command line; library consumers scan with
+//! [`report_iter`], configured by the same [`Options`]
+
+extern crate clap;
+extern crate ffmpeg_next as ffmpeg;
+extern crate walkdir;
+
+mod cache;
+mod compare;
+mod csv;
+mod estimate;
+mod influx;
+mod json;
+mod kdl;
+mod lint;
+mod locale;
+mod manifest;
+mod profile;
+pub mod report;
+
+pub use report::{FieldDiff, MediaReport, ReportError};
+
+use clap::{CommandFactory, ErrorKind, Parser};
+use ffmpeg::codec::packet::side_data;
+use ffmpeg::software::scaling;
+use ffmpeg::{codec, ffi, format, frame, media, Dictionary};
+use flate2::write::GzEncoder;
+use locale::NumberFormat;
+use profile::Phase;
+use rayon::prelude::*;
+use report::{
+    Captions, Category, ColorInfo, FirstKeyframe, FrameCount, FrameSizes, Interleave,
+    KeyframeCadence, Marker, PacketReport, SampleAspect, StreamKind, StreamReport,
+};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, instrument, warn};
+use walkdir::{DirEntry, WalkDir};
+
+/// Utility to generate reports on the media file contents for a folder
+/// which can be diffed using traditional tools, like diff
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, long_about = None)]
+pub struct Options {
+    /// Root directory to scan
+    #[clap(short, long, parse(from_os_str), value_name = "DIRECTORY")]
+    pub root_dir: PathBuf,
+
+    /// Include the color section (bit depth, HDR mastering metadata) for the video stream
+    #[clap(long)]
+    pub color_info: bool,
+
+    /// Include the application that muxed the file, read from the
+    /// `writing_app`, `muxing_app` or `encoder` (MP4 `©too`) container tags
+    #[clap(long)]
+    pub muxer_info: bool,
+
+    /// Include ffmpeg's confidence (0-100) in the detected container format.
+    /// 100 is a certain match of the file contents, 75 a match by mime type,
+    /// 50 by file extension only and 25 or less a guess from too little data
+    #[clap(long)]
+    pub probe_score: bool,
+
+    /// Flag files with `LOW-PROBE-SCORE` when ffmpeg's confidence in the
+    /// detected format is below N, as their metadata is often unreliable
+    #[clap(long, value_name = "N")]
+    pub min_probe_score: Option<i32>,
+
+    /// Include the capabilities of the input next to its format, e.g.
+    /// `Format: matroska,webm [seekable]`: whether it can be seeked, is a
+    /// live stream or lacks reliable timestamps
+    #[clap(long)]
+    pub format_flags: bool,
+
+    /// Drop the version numbers from the muxer, so files written by
+    /// different releases of the same tool report the same muxer
+    #[clap(long, requires = "muxer-info")]
+    pub normalize_muxer_version: bool,
+
+    /// How the video stream representing a file is picked for the codec and
+    /// for the color, frame count, efficiency and decoding checks: ffmpeg's
+    /// own scoring, the largest frame size or the highest bit rate
+    #[clap(long, arg_enum, default_value = "ffmpeg")]
+    pub best_video: BestVideo,
+
+    /// How the audio stream representing a file is picked: ffmpeg's own
+    /// scoring, the most channels or the highest bit rate
+    #[clap(long, arg_enum, default_value = "ffmpeg")]
+    pub best_audio: BestAudio,
+
+    /// Order in which discovered files are fed to the analyzer. A plain scan
+    /// writes the report in this order too, the modes comparing or checking
+    /// the whole library sort theirs by path
+    #[clap(long, arg_enum, default_value = "alpha")]
+    pub scan_order: ScanOrder,
+
+    /// Follow symbolic links during discovery, skipping links that form cycles
+    #[clap(long)]
+    pub follow_symlinks: bool,
+
+    /// Use ffmpeg's descriptive codec names instead of the short names
+    #[clap(long)]
+    pub codec_long_name: bool,
+
+    /// Enable checks that read packets from the file, such as the first
+    /// keyframe offset and embedded CEA-608/708 captions. These read far
+    /// more data than the default probe
+    #[clap(long)]
+    pub decode: bool,
+
+    /// Decode the first N frames of the video stream and flag `BLANK-VIDEO`
+    /// when every one of them is a single flat color, as left behind by a
+    /// failed capture. This is a heuristic: a file that opens on a long fade
+    /// from black is flagged too when N is small. Requires `--decode`
+    #[clap(long, value_name = "FRAMES", requires = "decode")]
+    pub blank_frames: Option<usize>,
+
+    /// Measure the keyframe spacing over the first two minutes of the video
+    /// stream and report whether it is regular enough to cut HLS/DASH
+    /// segments of a fixed length, flagging `IRREGULAR-KEYFRAMES` otherwise.
+    /// Requires `--decode`
+    #[clap(long, requires = "decode")]
+    pub keyframe_cadence: bool,
+
+    /// Report the smallest, largest and average compressed frame size of the
+    /// video stream, where spikes point at complex scenes or corruption.
+    /// Reads the packets of the whole file. Requires `--decode`
+    #[clap(long, requires = "decode")]
+    pub frame_sizes: bool,
+
+    /// Read the packets of the whole file in order and measure how far the
+    /// audio and video timestamps drift apart, flagging `POOR-INTERLEAVE`
+    /// when they exceed `--max-interleave-gap`. Poorly interleaved files
+    /// buffer during progressive playback. Requires `--decode`
+    #[clap(long, requires = "decode")]
+    pub interleave: bool,
+
+    /// Largest gap in seconds between the audio and video packets read
+    /// next to each other that still counts as well interleaved
+    #[clap(long, value_name = "SECONDS", default_value = "1.0")]
+    pub max_interleave_gap: f64,
+
+    /// Largest difference between the darkest and brightest pixel (0-255)
+    /// for which a frame still counts as blank
+    #[clap(long, value_name = "LEVELS", default_value = "10")]
+    pub blank_threshold: u8,
+
+    /// Compare the frame count the video stream declares against the one
+    /// implied by its duration and frame rate, flagging `FRAME-COUNT-MISMATCH`
+    /// when they diverge. Catches some truncated or badly concatenated files
+    #[clap(long)]
+    pub check_frame_count: bool,
+
+    /// Compare the sample aspect ratio the container declares for the video
+    /// stream against the one of the codec, reporting both and flagging
+    /// `SAR-CONFLICT` when both are set and disagree. Such files are often
+    /// played back stretched
+    #[clap(long)]
+    pub check_aspect: bool,
+
+    /// Allowed divergence between the declared and estimated frame count,
+    /// in percent of the estimate
+    #[clap(long, value_name = "PERCENT", default_value = "1.0")]
+    pub frame_count_tolerance: f64,
+
+    /// Character encoding of the written report. Characters the encoding
+    /// can't represent are written as `\u{XXXX}` escapes
+    #[clap(long, arg_enum, default_value = "utf-8")]
+    pub output_encoding: OutputEncoding,
+
+    /// Write the output to FILE instead of stdout. `-` is stdout as well, for
+    /// scripts that always pass the flag
+    #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Split the report written to `--output` into files of at most SIZE
+    /// bytes, e.g. `100MB` (base 10 `KB`, `MB` and `GB` work too). The files
+    /// are numbered before the extension, `report.json` becomes
+    /// `report.000.json`, `report.001.json` and so on. A file rolls over to
+    /// the next only between two files of the report (or groups of
+    /// `--group-by-codec`), so a single entry larger than SIZE gets a file of
+    /// its own that exceeds it. Every CSV file starts with the header row.
+    /// SIZE counts the bytes before compression. Standard output can't be
+    /// split
+    #[clap(
+        long,
+        parse(try_from_str = parse_size),
+        value_name = "SIZE",
+        requires = "output",
+        conflicts_with_all = &["batch-size", "compare", "lint", "verify"]
+    )]
+    pub split_size: Option<u64>,
+
+    /// Compress the written output, works with every output format
+    #[clap(long, arg_enum, default_value = "none")]
+    pub compress: OutputCompression,
+
+    /// Don't end the output with a newline, in every output format
+    #[clap(long)]
+    pub no_trailing_newline: bool,
+
+    /// Instead of the report, print a list of problems found in the library
+    /// (missing streams, mislabeled containers) and exit non-zero if any
+    #[clap(long)]
+    pub lint: bool,
+
+    /// Follow the markers of every flagged file in the human report, and
+    /// every `--lint` problem, with the reason each was flagged and the
+    /// values that triggered it. The JSON output always carries the marker
+    /// reasons
+    #[clap(long)]
+    pub explain: bool,
+
+    /// Output format of the report
+    #[clap(long, arg_enum, default_value = "human")]
+    pub format: OutputFormat,
+
+    /// Skip files modified within the last N seconds, which are likely still
+    /// being written, e.g. by an in-progress download
+    #[clap(long, value_name = "SECONDS")]
+    pub skip_recent: Option<u64>,
+
+    /// Also check that the size of every file stays the same over a short
+    /// pause, skipping the files that are still growing
+    #[clap(long)]
+    pub skip_growing: bool,
+
+    /// Leave out files whose duration is exactly zero, such as stray
+    /// thumbnails. Files with an unknown duration are still reported
+    #[clap(long)]
+    pub exclude_zero_duration: bool,
+
+    /// Comma separated columns of `--format csv`, in order. Every column is
+    /// written when left out, the other formats don't take it
+    #[clap(long, arg_enum, use_value_delimiter = true, value_name = "COLUMNS")]
+    pub columns: Vec<csv::Column>,
+
+    /// With `--format json`, write one object per stream with the fields of
+    /// the file repeated instead of nesting the streams, for flat schemas
+    #[clap(long)]
+    pub json_flatten_streams: bool,
+
+    /// Write the numbers of the human report and of `--estimate` the way a
+    /// locale does, e.g. `de_DE` for `1.234,5`. Only the language counts;
+    /// pass `"$LC_NUMERIC"` to follow the environment. The other formats are
+    /// unaffected, as are the marker reasons of `--explain`, which are the
+    /// same text as in the JSON output
+    #[clap(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
+    /// Organize the human report under a header per video codec, with the
+    /// number of files in each group. Files without video are grouped under
+    /// `audio-only`. The other formats don't take it
+    #[clap(long)]
+    pub group_by_codec: bool,
+
+    /// Discover, analyze and write the files N at a time instead of all at
+    /// once, so the memory use stays the same however large the library is.
+    /// Smaller batches use less memory but leave workers idle at the end of
+    /// every batch, and `--skip-growing` pauses once per batch. Batches are
+    /// written in the order the directory walk finds them, with
+    /// `--scan-order` applying within each batch
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &["compare", "group-by-codec", "lint", "manifest-out", "verify"]
+    )]
+    pub batch_size: Option<usize>,
+
+    /// Only let ffmpeg analyze the first N seconds of each file. Much faster
+    /// on slow storage, but durations that aren't stored in the container
+    /// header become estimates and are reported as `~HH:MM:SS (sampled)`
+    #[clap(long, value_name = "SECONDS")]
+    pub sample_seconds: Option<u64>,
+
+    /// Compare the root directory against a second copy of the library,
+    /// printing only the files and fields that differ between the two
+    #[clap(long, parse(from_os_str), value_name = "DIRECTORY")]
+    pub compare: Option<PathBuf>,
+
+    /// Render the compare output as aligned `field | copy A | copy B` columns
+    #[clap(long, requires = "compare")]
+    pub side_by_side: bool,
+
+    /// Also show up to N unchanged fields before and after every changed
+    /// field of the compare output, like the context lines of `diff -U`
+    #[clap(long, value_name = "FIELDS", default_value = "0", requires = "compare")]
+    pub context: usize,
+
+    /// Report a file that only exists in one copy under one path and in the
+    /// other under another as renamed, when the size and structural hash of
+    /// the two match, rather than as removed and added
+    #[clap(long, requires = "compare")]
+    pub detect_renames: bool,
+
+    /// Match the files of the two copies by their size and structural hash
+    /// alone, wherever they are stored, and list only the content missing
+    /// from either copy. Answers whether both hold the same content after
+    /// one was reorganized
+    #[clap(long, requires = "compare", conflicts_with = "detect-renames")]
+    pub ignore_paths: bool,
+
+    /// End the compare output with a summary: how many files were added,
+    /// removed and changed, and which fields changed in the most files
+    #[clap(long, requires = "compare")]
+    pub compare_threshold_report: bool,
+
+    /// When to highlight the differing values of the side-by-side view
+    #[clap(long, arg_enum, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Report the bits spent per pixel of the video stream, bit rate divided
+    /// by width x height x frame rate, which is comparable across resolutions
+    #[clap(long)]
+    pub efficiency: bool,
+
+    /// Flag `BITRATE-OUTLIER` when the bits per pixel fall below this value,
+    /// suggesting an over-compressed file. Requires `--efficiency`
+    #[clap(long, value_name = "BITS", requires = "efficiency")]
+    pub efficiency_min: Option<f64>,
+
+    /// Flag `BITRATE-OUTLIER` when the bits per pixel exceed this value,
+    /// suggesting a needlessly large file. Requires `--efficiency`
+    #[clap(long, value_name = "BITS", requires = "efficiency")]
+    pub efficiency_max: Option<f64>,
+
+    /// Report how far apart the audio and video streams of a file start, the
+    /// latest minus the earliest stream start time. A large spread points at
+    /// padding or sync compensation applied while muxing
+    #[clap(long)]
+    pub start_spread: bool,
+
+    /// Flag `START-SPREAD` when the streams start more than this many
+    /// seconds apart. Only checked with `--start-spread`
+    #[clap(long, value_name = "SECONDS", default_value = "0.1")]
+    pub max_start_spread: f64,
+
+    /// Tag every file with the kind of content it likely holds: `music` when
+    /// it only has audio, otherwise by duration `movie` from
+    /// `--movie-minutes` up, `clip` below `--clip-minutes` and `episode` in
+    /// between. Files of unknown duration are `unknown`
+    #[clap(long)]
+    pub categorize: bool,
+
+    /// Shortest duration of a movie for `--categorize`, in minutes
+    #[clap(long, value_name = "MINUTES", default_value = "70")]
+    pub movie_minutes: u64,
+
+    /// Duration below which a video is a clip for `--categorize`, in minutes
+    #[clap(long, value_name = "MINUTES", default_value = "15")]
+    pub clip_minutes: u64,
+
+    /// Include ffmpeg's own error message, e.g. `Invalid data found when
+    /// processing input`, in the warning for files that couldn't be opened.
+    /// With `--format json` those files are also written as entries with the
+    /// message in their `error` key, as with `--treat-errors-as-entries`
+    #[clap(long)]
+    pub preserve_ffmpeg_errors: bool,
+
+    /// Keep files that couldn't be analyzed in the report as an entry with
+    /// only the path, the size and an `ERROR` line, so every discovered file
+    /// is listed. The reason is ffmpeg's own with `--preserve-ffmpeg-errors`.
+    /// `--lint` lists them as `unreadable`
+    #[clap(long)]
+    pub treat_errors_as_entries: bool,
+
+    /// Also write a restore verification manifest of the scanned files
+    /// (relative path, size, duration, structural hash and optional
+    /// checksum) to FILE
+    #[clap(long, parse(from_os_str), value_name = "FILE")]
+    pub manifest_out: Option<PathBuf>,
+
+    /// Include a SHA-256 checksum of the complete contents of every file in
+    /// the manifest. This reads every byte of the library
+    #[clap(long, requires = "manifest-out")]
+    pub checksum: bool,
+
+    /// Instead of the report, verify the root directory against a manifest
+    /// written by `--manifest-out`, listing every difference and exiting
+    /// non-zero if any are found
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "FILE",
+        conflicts_with = "manifest-out"
+    )]
+    pub verify: Option<PathBuf>,
+
+    /// Keep the reports in FILE between runs and reuse the ones of the
+    /// files whose size and modification time haven't changed since. The
+    /// cache is rewritten after every complete scan and ignored by runs
+    /// with other analysis options
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "FILE",
+        conflicts_with = "batch-size"
+    )]
+    pub cache: Option<PathBuf>,
+
+    /// Probe every file with a cached report and analyze it again when its
+    /// structural hash no longer matches the cached one, logging the change.
+    /// The probe only opens the container and reads the stream headers,
+    /// skipping the packet reading of `--decode`. With `--decode` that is
+    /// much cheaper than the full analysis, without it the probe is the
+    /// full analysis and the cache saves nothing
+    #[clap(long, requires = "cache")]
+    pub rescan_on_mismatch: bool,
+
+    /// Print a breakdown of the time spent per phase of the scan
+    /// (discovery, opening, stream analysis, packet reading, hashing) to
+    /// stderr when done
+    #[clap(long)]
+    pub profile_stats: bool,
+
+    /// Pin every worker thread to a CPU core of its own. No speedup is
+    /// promised, compare `--profile-stats` with and without it on the
+    /// machine at hand. Needs the `pin-threads` cargo feature
+    #[cfg(feature = "pin-threads")]
+    #[clap(long)]
+    pub pin_threads: bool,
+
+    /// Don't analyze anything, only discover the files, benchmark reading
+    /// from the storage and print how long a scan with the other options
+    /// would take
+    #[clap(long)]
+    pub estimate: bool,
+}
+
+/// Rendering used for the report
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum OutputFormat {
+    /// Indented text meant for reading and diffing
+    Human,
+    /// InfluxDB line protocol, one point per file tagged by its path and
+    /// timestamped at scan time
+    Influx,
+    /// KDL document with a `file` node per file and a child node per stream
+    Kdl,
+    /// One JSON object per line and file, streams nested in an array
+    Json,
+    /// A header row, then one row per file with the `--columns`
+    Csv,
+}
+
+/// Use of ANSI colors in the output
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum ColorChoice {
+    /// Only when the report goes to stdout and stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+/// Criterion for the video stream that represents a file
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum BestVideo {
+    /// `av_find_best_stream`, which favors the stream with the most frames
+    Ffmpeg,
+    /// Most pixels per frame
+    Resolution,
+    Bitrate,
+}
+
+impl BestVideo {
+    /// Score ranking the streams, `None` to defer to ffmpeg
+    fn score(&self) -> Option<fn(&ffmpeg::Stream) -> i64> {
+        match self {
+            BestVideo::Ffmpeg => None,
+            BestVideo::Resolution => Some(|stream| {
+                stream_resolution(&stream.parameters())
+                    .map_or(0, |(width, height)| i64::from(width) * i64::from(height))
+            }),
+            BestVideo::Bitrate => Some(stream_bit_rate),
+        }
+    }
+}
+
+/// Criterion for the audio stream that represents a file
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum BestAudio {
+    /// `av_find_best_stream`, which favors the stream with the most frames
+    Ffmpeg,
+    Channels,
+    Bitrate,
+}
+
+impl BestAudio {
+    /// Score ranking the streams, `None` to defer to ffmpeg
+    fn score(&self) -> Option<fn(&ffmpeg::Stream) -> i64> {
+        match self {
+            BestAudio::Ffmpeg => None,
+            BestAudio::Channels => {
+                Some(|stream| i64::from(unsafe { (*stream.parameters().as_ptr()).channels }))
+            }
+            BestAudio::Bitrate => Some(stream_bit_rate),
+        }
+    }
+}
+
+/// Discovery ordering for the analysis pass
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum ScanOrder {
+    /// Alphabetical by path, for reproducible runs
+    Alpha,
+    /// Most recently modified first
+    Mtime,
+    /// Largest files first
+    Size,
+}
+
+/// Text encoding for the final report
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum OutputEncoding {
+    #[clap(name = "utf-8")]
+    Utf8,
+    Latin1,
+}
+
+/// Compression applied to the report, whether it goes to stdout or to the
+/// `--output` file. Both codecs write a single stream that can be
+/// decompressed on the fly (`zcat`, `zstdcat`), so the line based formats
+/// (human, influx, JSON, CSV) can be processed while they are being
+/// decompressed. KDL is one document and has to be read in full
+#[derive(clap::ArgEnum, Clone, Debug)]
+pub enum OutputCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Options {
+    /// The defaults of the command line for scanning `root_dir`, to adjust
+    /// through the fields before handing them to `report_iter`
+    pub fn new(root_dir: impl Into<PathBuf>) -> Options {
+        let mut options = Options::parse_from(["media-server-diff", "--root-dir", "."]);
+        options.root_dir = root_dir.into();
+        options
+    }
+
+    /// Reject the combinations clap can't rule out on its own, because they
+    /// depend on the value of another option
+    pub fn check(&self) -> Result<(), clap::Error> {
+        let conflict =
+            |message: &str| Err(Options::command().error(ErrorKind::ArgumentConflict, message));
+
+        if !self.columns.is_empty() && !matches!(self.format, OutputFormat::Csv) {
+            return conflict("--columns only applies to --format csv");
+        }
+        if self.group_by_codec && !matches!(self.format, OutputFormat::Human) {
+            return conflict("--group-by-codec only applies to --format human");
+        }
+        if self.split_size.is_some() && output_file(self).is_none() {
+            return conflict("--split-size needs a file to split, not --output -");
+        }
+        Ok(())
+    }
+}
+
+/// Run what the command line asked for, returning the exit code
+pub fn run_command_line(args: &Options) -> i32 {
+    #[cfg(feature = "pin-threads")]
+    if args.pin_threads {
+        pin_worker_threads();
+    }
+
+    let exit_code = run(args);
+    if args.profile_stats {
+        eprint!("{}", profile::render());
+    }
+    exit_code
+}
+
+/// Build the global thread pool with one worker per core, each pinned to it
+#[cfg(feature = "pin-threads")]
+fn pin_worker_threads() {
+    let cores = match core_affinity::get_core_ids() {
+        Some(cores) if !cores.is_empty() => cores,
+        _ => {
+            warn!("Could not list the CPU cores, leaving the worker threads unpinned");
+            return;
+        }
+    };
+
+    let count = cores.len();
+    let built = rayon::ThreadPoolBuilder::new()
+        .num_threads(count)
+        .start_handler(move |index| {
+            if !core_affinity::set_for_current(cores[index % count]) {
+                warn!(index, "Could not pin worker thread");
+            }
+        })
+        .build_global();
+    if let Err(error) = built {
+        warn!(%error, "Could not pin the worker threads");
+    }
+}
+
+/// Run the selected mode, returning the exit code of the process
+fn run(args: &Options) -> i32 {
+    info!("Path: {}", args.root_dir.display());
+
+    if args.estimate {
+        let files: Vec<(PathBuf, u64)> = discover_entries(args)
+            .into_iter()
+            .map(|entry| {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                (entry.into_path(), size)
+            })
+            .collect();
+        // Hashing and the per packet checks read every byte, everything else
+        // (including the rest of `--decode`) stays within the first packets
+        let full_read = args.checksum || args.frame_sizes || args.interleave;
+        let estimate = estimate::Estimate::new(&files, full_read);
+        write_report(&estimate.render(&number_format(args)), args);
+        return 0;
+    }
+
+    let scan_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    if let Some(batch_size) = args.batch_size {
+        write_batches(args, batch_size.max(1), scan_time);
+        return 0;
+    }
+
+    // A plain scan is written while it goes, the other modes need all of
+    // the reports at once
+    let plain = args.manifest_out.is_none()
+        && args.verify.is_none()
+        && !args.lint
+        && args.compare.is_none()
+        && !args.group_by_codec
+        && args.split_size.is_none();
+    if plain {
+        write_streamed(args, scan_time);
+        return 0;
+    }
+
+    let reports = generate_reports(args);
+
+    if let Some(manifest_path) = &args.manifest_out {
+        let entries = manifest_entries(&reports, args, args.checksum);
+        fs::write(manifest_path, manifest::render(&entries)).expect("Unable to write manifest");
+    }
+
+    if let Some(manifest_path) = &args.verify {
+        let expected = match fs::read_to_string(manifest_path)
+            .map_err(|error| error.to_string())
+            .and_then(|contents| manifest::parse(&contents))
+        {
+            Ok(expected) => expected,
+            Err(error) => {
+                error!(manifest = %manifest_path.display(), %error, "Unable to read manifest");
+                return 2;
+            }
+        };
+
+        // Only hash the restored files if the manifest has something to compare against
+        let checksums = expected.iter().any(|entry| entry.checksum.is_some());
+        let (unreadable, readable): (Vec<MediaReport>, Vec<MediaReport>) = reports
+            .into_iter()
+            .partition(|report| report.error.is_some());
+        let unreadable: Vec<PathBuf> = unreadable
+            .iter()
+            .map(|report| report.relative_path(&args.root_dir).to_path_buf())
+            .collect();
+        let problems = manifest::verify(
+            &expected,
+            &manifest_entries(&readable, args, checksums),
+            &unreadable,
+        );
+        if !problems.is_empty() {
+            write_report(&problems.join("\n"), args);
+            return 1;
+        }
+    } else if args.lint {
+        let problems: Vec<String> = reports
+            .iter()
+            .flat_map(|report| {
+                lint::lint(report).into_iter().map(move |problem| {
+                    let mut line =
+                        format!("{}: {}", report.path.to_string_lossy(), problem.category());
+                    if args.explain {
+                        line.push_str(&format!("\n\t{}", problem.reason(report)));
+                    }
+                    line
+                })
+            })
+            .collect();
+
+        if !problems.is_empty() {
+            write_report(&problems.join("\n"), args);
+            return 1;
+        }
+    } else if let Some(other_dir) = &args.compare {
+        let other_args = Options {
+            root_dir: other_dir.clone(),
+            ..args.clone()
+        };
+        let other_reports = generate_reports(&other_args);
+
+        let changes = if args.ignore_paths {
+            compare::compare_content(&args.root_dir, &reports, other_dir, &other_reports)
+        } else {
+            compare::compare(
+                &args.root_dir,
+                &reports,
+                other_dir,
+                &other_reports,
+                args.context,
+                args.detect_renames,
+            )
+        };
+        let mut sections = Vec::new();
+        if !changes.is_empty() {
+            sections.push(if args.side_by_side {
+                let color = match args.color {
+                    // A report going to a file is never shown on the terminal
+                    ColorChoice::Auto => output_file(args).is_none() && io::stdout().is_terminal(),
+                    ColorChoice::Always => true,
+                    ColorChoice::Never => false,
+                };
+                compare::render_side_by_side(&changes, color)
+            } else {
+                compare::render(&changes)
+            });
+        }
+        if args.compare_threshold_report {
+            sections.push(compare::summarize(&changes));
+        }
+        if !sections.is_empty() {
+            write_report(&sections.join("\n\n"), args);
+        }
+    } else {
+        let rendered = render_reports(&reports, args, scan_time);
+        let header = match args.format {
+            OutputFormat::Csv => Some(csv::header(csv_columns(args))),
+            _ => None,
+        };
+
+        match (args.split_size, output_file(args)) {
+            // Standard output can't be split, `Options::check` rejects it
+            (Some(size), Some(output)) => {
+                let encoding = &args.output_encoding;
+                let header_size = header
+                    .as_ref()
+                    .map_or(0, |header| encode_report(header, encoding).len() + 1);
+                for (index, part) in split_records(&rendered, header_size, size, encoding)
+                    .into_iter()
+                    .enumerate()
+                {
+                    let part_args = Options {
+                        output: Some(split_path(output, index)),
+                        ..args.clone()
+                    };
+                    let lines: Vec<&str> = header.iter().chain(part).map(String::as_str).collect();
+                    write_report(&lines.join("\n"), &part_args);
+                }
+            }
+            _ => {
+                let lines: Vec<String> = header.into_iter().chain(rendered).collect();
+                write_report(&lines.join("\n"), args);
+            }
+        }
+    }
+
+    0
+}
+
+/// Notation of the numbers of the human output, from `--locale`
+fn number_format(args: &Options) -> NumberFormat {
+    args.locale
+        .as_deref()
+        .map_or(NumberFormat::C, NumberFormat::for_locale)
+}
+
+/// Render every report in the output format, one entry per line or block
+fn render_reports(reports: &[MediaReport], args: &Options, scan_time: Duration) -> Vec<String> {
+    let numbers = number_format(args);
+    match args.format {
+        OutputFormat::Human if args.group_by_codec => report::group_by_codec(reports)
+            .into_iter()
+            .map(|(codec, group)| {
+                let mut lines = vec![format!("== {} ({} files) ==", codec, group.len())];
+                lines.extend(
+                    group
+                        .into_iter()
+                        .map(|report| report.render(&numbers, args.explain)),
+                );
+                lines.join("\n")
+            })
+            .collect(),
+        OutputFormat::Human => reports
+            .iter()
+            .map(|report| report.render(&numbers, args.explain))
+            .collect(),
+        OutputFormat::Influx => reports
+            .iter()
+            .map(|report| influx::render(report, scan_time.as_nanos()))
+            .collect(),
+        OutputFormat::Kdl => reports.iter().map(kdl::render).collect(),
+        OutputFormat::Json if args.json_flatten_streams => {
+            reports.iter().flat_map(json::render_flattened).collect()
+        }
+        OutputFormat::Json => reports.iter().map(json::render).collect(),
+        OutputFormat::Csv => reports
+            .iter()
+            .map(|report| csv::render(report, csv_columns(args)))
+            .collect(),
+    }
+}
+
+/// Columns of `--format csv`, all of them unless `--columns` picks some
+fn csv_columns(args: &Options) -> &[csv::Column] {
+    if args.columns.is_empty() {
+        &csv::Column::ALL
+    } else {
+        &args.columns
+    }
+}
+
+/// Parse a size of `--split-size`, a number of bytes with an optional base 10
+/// `B`, `KB`, `MB` or `GB` unit
+fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("\"{}\" doesn't start with a number", text))?;
+
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        unit => return Err(format!("unknown size unit \"{}\"", unit)),
+    };
+    match number.checked_mul(multiplier) {
+        Some(0) | None => Err(format!("\"{}\" isn't a usable size", text)),
+        Some(size) => Ok(size),
+    }
+}
+
+/// Split the records into runs that fit `size` bytes once encoded and
+/// joined by newlines, after a header of `header_size` bytes. A record that
+/// doesn't fit on its own gets a run of its own. There is always at least
+/// one run
+fn split_records<'a>(
+    records: &'a [String],
+    header_size: usize,
+    size: u64,
+    encoding: &OutputEncoding,
+) -> Vec<&'a [String]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut used = header_size as u64;
+    for (index, record) in records.iter().enumerate() {
+        let record_size = encode_report(record, encoding).len() as u64 + 1;
+        if index > start && used + record_size > size {
+            runs.push(&records[start..index]);
+            start = index;
+            used = header_size as u64;
+        }
+        used += record_size;
+    }
+    runs.push(&records[start..]);
+
+    runs
+}
+
+/// Path of part `index` of a split output, numbered before the extension
+fn split_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{:03}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.{:03}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Scan and write the library `batch_size` files at a time for
+/// `--batch-size`. Discovery walks in path order and every batch is sorted
+/// by path, so the output is the same as written at once, with the batches
+/// and the lines within them separated by newlines
+fn write_batches(args: &Options, batch_size: usize, scan_time: Duration) {
+    let mut writer = ReportWriter::new(args);
+    if let OutputFormat::Csv = args.format {
+        writer.write(&csv::header(csv_columns(args)));
+    }
+
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut flush = |batch: Vec<DirEntry>| {
+        let reports = analyze_entries(batch, args);
+        writer.write(&render_reports(&reports, args, scan_time).join("\n"));
+    };
+    discover(args, |entry| {
+        batch.push(entry);
+        if batch.len() == batch_size {
+            flush(std::mem::replace(
+                &mut batch,
+                Vec::with_capacity(batch_size),
+            ));
+        }
+        true
+    });
+    if !batch.is_empty() {
+        flush(batch);
+    }
+
+    writer.finish();
+}
+
+/// Write the report of a plain scan file by file, as `report_iter` yields
+/// them, so no more than its window of reports is held in memory. The
+/// report comes in scan order, the files are sorted before the analysis
+fn write_streamed(args: &Options, scan_time: Duration) {
+    let mut writer = ReportWriter::new(args);
+    if let OutputFormat::Csv = args.format {
+        writer.write(&csv::header(csv_columns(args)));
+    }
+
+    for report in kept_reports(report_iter(args), args) {
+        let rendered = render_reports(std::slice::from_ref(&report), args, scan_time);
+        writer.write(&rendered.join("\n"));
+    }
+
+    writer.finish();
+}
+
+/// The report of the modes writing it piece by piece while the scan goes on,
+/// in the requested encoding and compression. The pieces are separated by
+/// newlines, so the output is the same as if written in one go
+struct ReportWriter<'a> {
+    args: &'a Options,
+    out: Box<dyn Write>,
+    written: bool,
+}
+
+impl<'a> ReportWriter<'a> {
+    /// Start the output, truncating the `--output` file
+    fn new(args: &'a Options) -> ReportWriter<'a> {
+        let out: Box<dyn Write> = match output_file(args) {
+            Some(path) => Box::new(io::BufWriter::new(
+                fs::File::create(path).expect("Unable to write report"),
+            )),
+            None => Box::new(io::stdout()),
+        };
+        let out: Box<dyn Write> = match args.compress {
+            OutputCompression::None => out,
+            OutputCompression::Gzip => {
+                Box::new(GzEncoder::new(out, flate2::Compression::default()))
+            }
+            OutputCompression::Zstd => Box::new(
+                zstd::stream::Encoder::new(out, 0)
+                    .expect("Unable to write report")
+                    .auto_finish(),
+            ),
+        };
+
+        ReportWriter {
+            args,
+            out,
+            written: false,
+        }
+    }
+
+    /// Append a piece of the report, skipping empty ones
+    fn write(&mut self, contents: &str) {
+        if contents.is_empty() {
+            return;
+        }
+
+        if self.written {
+            self.emit("\n");
+        }
+        self.emit(contents);
+        self.written = true;
+    }
+
+    /// End the report in a newline unless it is empty or
+    /// `--no-trailing-newline` is set. Dropping the writer finishes the
+    /// compressed stream
+    fn finish(mut self) {
+        if self.written && !self.args.no_trailing_newline {
+            self.emit("\n");
+        }
+        self.out.flush().expect("Unable to write report");
+    }
+
+    fn emit(&mut self, contents: &str) {
+        let encoded = encode_report(contents, &self.args.output_encoding);
+        self.out
+            .write_all(&encoded)
+            .expect("Unable to write report");
+    }
+}
+
+/// Manifest entries of the scanned files, hashing their full contents when
+/// `checksums` is set
+fn manifest_entries(
+    reports: &[MediaReport],
+    args: &Options,
+    checksums: bool,
+) -> Vec<manifest::Entry> {
+    reports
+        .par_iter()
+        .map(|report| {
+            let checksum = if checksums {
+                profile::time(Phase::Hashing, || manifest::checksum(&report.path))
+                    .map_err(|error| warn!(path = ?report.path, %error, "Unable to checksum file"))
+                    .ok()
+            } else {
+                None
+            };
+            manifest::Entry::new(&args.root_dir, report, checksum)
+        })
+        .collect()
+}
+
+/// Write the final report to the output in the requested encoding and compression,
+/// ending in a newline unless it is empty or `--no-trailing-newline` is set
+fn write_report(contents: &str, args: &Options) {
+    let mut contents = contents.to_owned();
+    if !contents.is_empty() && !args.no_trailing_newline {
+        contents.push('\n');
+    }
+    let encoded = encode_report(&contents, &args.output_encoding);
+
+    let written = match output_file(args) {
+        Some(path) => fs::File::create(path)
+            .and_then(|mut file| write_compressed(&mut file, &encoded, &args.compress)),
+        _ => write_compressed(&mut io::stdout().lock(), &encoded, &args.compress),
+    };
+    written.expect("Unable to write report");
+}
+
+/// File the report is written to, `None` for stdout
+fn output_file(args: &Options) -> Option<&Path> {
+    args.output
+        .as_deref()
+        .filter(|path| *path != Path::new("-"))
+}
+
+fn write_compressed(
+    out: &mut impl Write,
+    encoded: &[u8],
+    compress: &OutputCompression,
+) -> io::Result<()> {
+    match compress {
+        OutputCompression::None => out.write_all(encoded),
+        OutputCompression::Gzip => {
+            let mut encoder = GzEncoder::new(out, flate2::Compression::default());
+            encoder
+                .write_all(encoded)
+                .and_then(|_| encoder.finish().map(drop))
+        }
+        OutputCompression::Zstd => zstd::stream::copy_encode(encoded, out, 0),
+    }
+}
+
+fn generate_reports(args: &Options) -> Vec<MediaReport> {
+    if !args.root_dir.is_dir() {}
+
+    let entries = profile::time(Phase::Discovery, || discover_entries(args));
+    analyze_entries(entries, args)
+}
+
+/// Analyze the discovered files that aren't still being written, in scan
+/// order, returning the reports sorted by path
+fn analyze_entries(entries: Vec<DirEntry>, args: &Options) -> Vec<MediaReport> {
+    let paths = profile::time(Phase::Discovery, || scan_order_paths(entries, args));
+    let mut reports: Vec<MediaReport> =
+        kept_reports(analyze_iter(paths.into_iter(), args), args).collect();
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+    reports
+}
+
+/// The reports to write out of the analysis results, logging the files
+/// that failed and keeping them as placeholders with
+/// `--treat-errors-as-entries`
+fn kept_reports<'a>(
+    results: impl Iterator<Item = Result<MediaReport, ReportError>> + 'a,
+    args: &'a Options,
+) -> impl Iterator<Item = MediaReport> + 'a {
+    results
+        .filter_map(move |result| match result {
+            Ok(report) => Some(report),
+            Err(error) => {
+                if args.preserve_ffmpeg_errors {
+                    warn!(path = ?error.path, error = %error.reason, "Error processing file, ignoring");
+                } else {
+                    warn!(path = ?error.path, "Error processing file, ignoring");
+                }
+
+                if keeps_failed(args) {
+                    let reason = if args.preserve_ffmpeg_errors {
+                        error.reason
+                    } else {
+                        String::from("ffmpeg could not open the file")
+                    };
+                    let size = fs::metadata(&error.path).map(|m| m.len()).unwrap_or(0);
+                    Some(MediaReport::failed(error.path, size, reason))
+                } else {
+                    None
+                }
+            }
+        })
+        .filter(|report| !(args.exclude_zero_duration && report.duration == Some(Duration::ZERO)))
+}
+
+/// Report the files that couldn't be analyzed as placeholder entries
+fn keeps_failed(args: &Options) -> bool {
+    args.treat_errors_as_entries
+        // Verification tells the files it couldn't open from the missing ones
+        || args.verify.is_some()
+        // The JSON output is where the preserved errors are meant to be read
+        || (args.preserve_ffmpeg_errors && matches!(args.format, OutputFormat::Json))
+}
+
+/// Results the workers can hand over before the iterator takes them, the
+/// workers wait for room beyond that
+const REPORT_BUFFER: usize = 64;
+
+/// Furthest a worker may start ahead of the next result to yield. Files
+/// finished out of order wait in the iterator until the slower ones before
+/// them are done, so this bounds how many reports are held at once
+const REORDER_WINDOW: usize = 256;
+
+/// Discover and analyze the files under `args.root_dir` with the options of
+/// the command line, yielding a result per file as the rayon workers finish
+/// them, for consumers that process the reports one by one instead of
+/// holding millions of them. In the default alpha order the walk feeds the
+/// analysis through a bounded channel as it goes, at most `REORDER_WINDOW`
+/// results are in memory at a time. The results come in `--scan-order`,
+/// which for the alpha order is the path order of the rendered report. A
+/// file whose analysis panics yields an error
+pub fn report_iter(args: &Options) -> impl Iterator<Item = Result<MediaReport, ReportError>> {
+    analyze_iter(scan_paths(args), args)
+}
+
+/// Paths of the files to analyze, in scan order. Only the alpha order walks
+/// lazily, the mtime and size orders need every entry before the first, as
+/// does `--skip-growing` to compare the sizes after a single pause
+fn scan_paths(args: &Options) -> Box<dyn Iterator<Item = PathBuf> + Send> {
+    if !matches!(args.scan_order, ScanOrder::Alpha) || args.skip_growing {
+        let entries = profile::time(Phase::Discovery, || discover_entries(args));
+        let paths = profile::time(Phase::Discovery, || scan_order_paths(entries, args));
+        return Box::new(paths.into_iter());
+    }
+
+    let (sender, receiver) = mpsc::sync_channel(REPORT_BUFFER);
+    let args = args.clone();
+    thread::spawn(move || {
+        let now = SystemTime::now();
+        let window = args.skip_recent.map(Duration::from_secs);
+        discover(&args, |entry| {
+            if modified_within(&entry, now, window) {
+                info!(path = ?entry.path(), "Skipping file that is still being written");
+                return true;
+            }
+            // The receiving end only hangs up when the analysis stops early
+            sender.send(entry.into_path()).is_ok()
+        });
+    });
+    Box::new(receiver.into_iter())
+}
+
+/// Drop the discovered files that are still being written and sort the rest
+/// into scan order
+fn scan_order_paths(entries: Vec<DirEntry>, args: &Options) -> Vec<PathBuf> {
+    let mut entries = skip_in_progress(entries, args);
+    sort_entries(&mut entries, &args.scan_order);
+    debug!(num_paths = entries.len(), "Discovered path count");
+    entries.into_iter().map(DirEntry::into_path).collect()
+}
+
+/// Analyze the paths on the worker threads, see `report_iter`
+fn analyze_iter(
+    paths: impl Iterator<Item = PathBuf> + Send + 'static,
+    args: &Options,
+) -> impl Iterator<Item = Result<MediaReport, ReportError>> {
+    let (sender, receiver) = mpsc::sync_channel(REPORT_BUFFER);
+    let window = Arc::new(ScanWindow::new());
+    let args = args.clone();
+    let workers_window = Arc::clone(&window);
+    thread::spawn(move || {
+        let cache = args.cache.as_deref().map(|path| load_cache(path, &args));
+
+        // Bridging hands the paths out one by one in scan order, where
+        // `par_iter` would split the list and start halfway through it.
+        // Holding back the next path keeps the workers within the window
+        let window = Arc::clone(&workers_window);
+        paths
+            .enumerate()
+            .take_while(move |(index, _)| window.enter(*index))
+            .par_bridge()
+            .for_each_with(sender.clone(), |sender, (index, path)| {
+                let analyze = || match &cache {
+                    Some(cache) => analyze_cached(&path, &args, cache),
+                    None => analyze_path(&path, &args),
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(analyze)).unwrap_or_else(|_| {
+                    Err(ReportError {
+                        path: path.clone(),
+                        reason: String::from("the analysis panicked"),
+                    })
+                });
+                // The receiving end only hangs up when the caller stops early
+                let _ = sender.send((index, result));
+            });
+
+        // Still holding a sender, the iterator can't have ended, so a
+        // closed window means the caller stopped before the end of the scan
+        if let (Some(cache), Some(path)) = (&cache, &args.cache) {
+            if !workers_window.closed() {
+                if let Err(error) = cache.save(path) {
+                    warn!(cache = %path.display(), %error, "Unable to write the cache");
+                }
+            }
+        }
+        drop(sender);
+    });
+
+    InScanOrder {
+        receiver,
+        pending: BTreeMap::new(),
+        next: 0,
+        window,
+    }
+}
+
+/// Read the `--cache`, starting over with an empty one when it can't be used
+fn load_cache(path: &Path, args: &Options) -> cache::Cache {
+    let options = analysis_options(args);
+    cache::Cache::load(path, &options).unwrap_or_else(|error| {
+        warn!(cache = %path.display(), %error, "Ignoring the cache");
+        cache::Cache::new(&options)
+    })
+}
+
+/// Short hash of the options the analysis of a file depends on, along with
+/// the version of the program, so a cache is only used by runs that would
+/// make the same reports
+fn analysis_options(args: &Options) -> String {
+    let options: [&dyn fmt::Debug; 28] = [
+        &args.color_info,
+        &args.muxer_info,
+        &args.probe_score,
+        &args.min_probe_score,
+        &args.format_flags,
+        &args.normalize_muxer_version,
+        &args.codec_long_name,
+        &args.best_video,
+        &args.best_audio,
+        &args.sample_seconds,
+        &args.decode,
+        &args.blank_frames,
+        &args.blank_threshold,
+        &args.keyframe_cadence,
+        &args.frame_sizes,
+        &args.interleave,
+        &args.max_interleave_gap,
+        &args.check_frame_count,
+        &args.frame_count_tolerance,
+        &args.check_aspect,
+        &args.efficiency,
+        &args.efficiency_min,
+        &args.efficiency_max,
+        &args.start_spread,
+        &args.max_start_spread,
+        &args.categorize,
+        &args.movie_minutes,
+        &args.clip_minutes,
+    ];
+    let options = format!("{} {:?}", env!("CARGO_PKG_VERSION"), options);
+    Sha256::digest(options.as_bytes())[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Analyze a file through the `--cache`. A file whose size and modification
+/// time are the ones cached gets the cached report, with
+/// `--rescan-on-mismatch` only once a probe of its streams agrees with the
+/// structural hash of that report
+fn analyze_cached(
+    path: &PathBuf,
+    args: &Options,
+    cache: &cache::Cache,
+) -> Result<MediaReport, ReportError> {
+    let stamp = cache::Stamp::of(path);
+    let cached = stamp.and_then(|stamp| cache.get(path, stamp));
+
+    let report = match cached {
+        Some(cached) if !args.rescan_on_mismatch => cached.clone(),
+        Some(cached) => {
+            let probe_args = Options {
+                decode: false,
+                ..args.clone()
+            };
+            let probe = analyze_path(path, &probe_args)?;
+            if probe.structural_hash() == cached.structural_hash() {
+                cached.clone()
+            } else {
+                info!(
+                    path = ?path,
+                    cached = %cached.structural_hash(),
+                    probed = %probe.structural_hash(),
+                    "Structure changed since the report was cached, analyzing again"
+                );
+                if args.decode {
+                    analyze_path(path, args)?
+                } else {
+                    probe
+                }
+            }
+        }
+        None => analyze_path(path, args)?,
+    };
+
+    if let Some(stamp) = stamp {
+        cache.insert(cache::Entry {
+            stamp,
+            report: report.clone(),
+        });
+    }
+    Ok(report)
+}
+
+/// Index of the next result `InScanOrder` yields, shared with the workers
+/// to keep them within `REORDER_WINDOW` of it
+struct ScanWindow {
+    /// `None` once the iterator is dropped
+    next: Mutex<Option<usize>>,
+    moved: Condvar,
+}
+
+impl ScanWindow {
+    fn new() -> ScanWindow {
+        ScanWindow {
+            next: Mutex::new(Some(0)),
+            moved: Condvar::new(),
+        }
+    }
+
+    /// Wait until `index` is within the window, false when the iterator was
+    /// dropped and nothing more should be started
+    fn enter(&self, index: usize) -> bool {
+        let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match *next {
+                None => return false,
+                Some(next) if index < next + REORDER_WINDOW => return true,
+                Some(_) => {
+                    next = self
+                        .moved
+                        .wait(next)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+    }
+
+    /// The iterator was dropped
+    fn closed(&self) -> bool {
+        self.next
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none()
+    }
+
+    fn advance(&self, next: Option<usize>) {
+        *self.next.lock().unwrap_or_else(PoisonError::into_inner) = next;
+        self.moved.notify_all();
+    }
+}
+
+/// Puts the results of the workers back in scan order
+struct InScanOrder<T> {
+    receiver: mpsc::Receiver<(usize, T)>,
+    /// Results that arrived before the ones preceding them, bounded by the window
+    pending: BTreeMap<usize, T>,
+    next: usize,
+    window: Arc<ScanWindow>,
+}
+
+impl<T> Iterator for InScanOrder<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.pending.remove(&self.next) {
+                self.next += 1;
+                self.window.advance(Some(self.next));
+                return Some(item);
+            }
+
+            match self.receiver.recv() {
+                Ok((index, item)) => {
+                    self.pending.insert(index, item);
+                }
+                // The workers are gone, skip over any gap they left
+                Err(_) => {
+                    self.next = *self.pending.keys().next()?;
+                    self.window.advance(Some(self.next));
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for InScanOrder<T> {
+    fn drop(&mut self) {
+        self.window.advance(None);
+    }
+}
+
+/// Transcode the report into the requested output encoding, escaping any
+/// character that has no representation instead of replacing it
+fn encode_report(report: &str, encoding: &OutputEncoding) -> Vec<u8> {
+    match encoding {
+        OutputEncoding::Utf8 => report.as_bytes().to_vec(),
+        OutputEncoding::Latin1 => {
+            let mut encoded = Vec::with_capacity(report.len());
+            for c in report.chars() {
+                match u8::try_from(u32::from(c)) {
+                    Ok(byte) => encoded.push(byte),
+                    Err(_) => encoded.extend(format!("\\u{{{:04X}}}", u32::from(c)).bytes()),
+                }
+            }
+            encoded
+        }
+    }
+}
+
+/// Walk the root directory and collect the entries worth inspecting
+fn discover_entries(args: &Options) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    discover(args, |entry| {
+        entries.push(entry);
+        true
+    });
+    entries
+}
+
+/// Walk the root directory in path order, handing every entry worth
+/// inspecting to `found` as soon as it is seen, until it returns false.
+/// When following symlinks, only a link pointing back at one of its own
+/// ancestors is a cycle, which walkdir reports and we warn about. Any other
+/// directory that resolves to one already walked, whether reached through a
+/// link or not, is skipped quietly so its files are only reported once
+fn discover(args: &Options, mut found: impl FnMut(DirEntry) -> bool) {
+    let mut visited_dirs = HashSet::new();
+    let mut warned_links = HashSet::new();
+    let mut walker = WalkDir::new(&args.root_dir)
+        .follow_links(args.follow_symlinks)
+        .sort_by_file_name()
+        .into_iter();
+
+    while let Some(next) = walker.next() {
+        let entry = match next {
+            Ok(entry) => entry,
+            Err(error) => {
+                match (error.path(), error.loop_ancestor()) {
+                    (Some(link), Some(_)) => warn_symlink_cycle(&mut warned_links, link),
+                    _ => warn!(path = ?error.path(), %error, "Permissions error"),
+                }
+                continue;
+            }
+        };
+
+        if args.follow_symlinks && entry.file_type().is_dir() {
+            if let Ok(canonical) = entry.path().canonicalize() {
+                if !visited_dirs.insert(canonical) {
+                    debug!(path = ?entry.path(), "Directory already walked, skipping");
+                    walker.skip_current_dir();
+                    continue;
+                }
+            }
+        }
+
+        if should_inspect_file(&entry) && !found(entry) {
+            return;
+        }
+    }
+}
+
+/// Pause between the two size checks of `--skip-growing`
+const GROWTH_CHECK_DELAY: Duration = Duration::from_secs(2);
+
+/// Drop the files that look like they are still being written to, either
+/// because they were modified very recently or because they grew while
+/// pausing for `GROWTH_CHECK_DELAY`
+fn skip_in_progress(entries: Vec<DirEntry>, args: &Options) -> Vec<DirEntry> {
+    if args.skip_recent.is_none() && !args.skip_growing {
+        return entries;
+    }
+
+    let now = SystemTime::now();
+    let window = args.skip_recent.map(Duration::from_secs);
+    let (mut kept, mut skipped): (Vec<DirEntry>, Vec<DirEntry>) = entries
+        .into_iter()
+        .partition(|entry| !modified_within(entry, now, window));
+
+    if args.skip_growing {
+        let sizes: Vec<Option<u64>> = kept.iter().map(current_size).collect();
+        thread::sleep(GROWTH_CHECK_DELAY);
+
+        let (stable, growing) = kept
+            .into_iter()
+            .zip(sizes)
+            .partition::<Vec<_>, _>(|(entry, size)| current_size(entry) == *size);
+        kept = stable.into_iter().map(|(entry, _)| entry).collect();
+        skipped.extend(growing.into_iter().map(|(entry, _)| entry));
+    }
+
+    for entry in &skipped {
+        info!(path = ?entry.path(), "Skipping file that is still being written");
+    }
+    info!(skipped = skipped.len(), "Skipped in-progress files");
+
+    kept
+}
+
+/// Size of the file right now, bypassing the metadata walkdir cached
+fn current_size(entry: &DirEntry) -> Option<u64> {
+    fs::metadata(entry.path()).map(|m| m.len()).ok()
+}
+
+/// Modified within the `--skip-recent` window of `now`, false without one
+fn modified_within(entry: &DirEntry, now: SystemTime, window: Option<Duration>) -> bool {
+    let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+    match (window, modified) {
+        (Some(window), Some(modified)) => modified_recently(modified, now, window),
+        _ => false,
+    }
+}
+
+/// Modified within `window` of `now`. Modification times in the future
+/// count as recent, the clock of the writer may be ahead
+fn modified_recently(modified: SystemTime, now: SystemTime, window: Duration) -> bool {
+    match now.duration_since(modified) {
+        Ok(age) => age < window,
+        Err(_) => true,
+    }
+}
+
+/// Warn about a cyclic symlink, once per link
+fn warn_symlink_cycle(warned_links: &mut HashSet<PathBuf>, link: &Path) {
+    if warned_links.insert(link.to_path_buf()) {
+        warn!(link = %link.display(), "Skipping cyclic symlink");
+    }
+}
+
+/// Given a path, return a description of the media file that can be used
+/// to differentiate between multiple copies of the same data set that have
+/// diverged
+#[instrument(skip(args))]
+#[allow(clippy::ptr_arg)]
+fn analyze_path(path: &PathBuf, args: &Options) -> Result<MediaReport, ReportError> {
+    let input = profile::time(Phase::Open, || match args.sample_seconds {
+        Some(seconds) => ffmpeg::format::input_with_dictionary(path, sampling_options(seconds)),
+        None => ffmpeg::format::input(path),
+    });
+
+    match input {
+        Ok(mut context) => {
+            debug!(mime_types = context.format().mime_types().join(",").as_str());
+
+            if !context.format().mime_types().into_iter().any(|mime_type| {
+                // If mime types are available, ensure that they are valid for our purposes
+                mime_type.starts_with("audio") || mime_type.starts_with("video")
+            }) {}
+
+            let streams: Vec<StreamReport> = profile::time(Phase::Streams, || {
+                context
+                    .streams()
+                    .map(|stream| analyze_stream(&stream, args))
+                    .collect()
+            });
+
+            let probe_score = if args.probe_score || args.min_probe_score.is_some() {
+                Some(context.probe_score())
+            } else {
+                None
+            };
+
+            let format_flags = if args.format_flags {
+                // Not exposed by ffmpeg-next, read straight from the context
+                let (io_seekable, context_flags, input_flags) = unsafe {
+                    let raw = &*context.as_ptr();
+                    (
+                        !raw.pb.is_null() && (*raw.pb).seekable & ffi::AVIO_SEEKABLE_NORMAL != 0,
+                        raw.ctx_flags,
+                        (*raw.iformat).flags,
+                    )
+                };
+                Some(input_format_flags(io_seekable, context_flags, input_flags))
+            } else {
+                None
+            };
+
+            let muxer = if args.muxer_info {
+                container_muxer(&context.metadata(), args.normalize_muxer_version)
+            } else {
+                None
+            };
+
+            let editions = count_editions(
+                context
+                    .chapters()
+                    .filter_map(|chapter| chapter.metadata().get("edition").map(String::from)),
+            );
+
+            let best_video = best_stream(&context, media::Type::Video, args.best_video.score());
+            let best_audio = best_stream(&context, media::Type::Audio, args.best_audio.score());
+
+            let color = if args.color_info {
+                best_video
+                    .and_then(|index| context.stream(index))
+                    .map(|stream| collect_color_info(&stream))
+            } else {
+                None
+            };
+
+            let frame_count = if args.check_frame_count {
+                let container_duration = u64::try_from(context.duration())
+                    .ok()
+                    .map(|micros| micros as f64 / f64::from(ffi::AV_TIME_BASE));
+                best_video
+                    .and_then(|index| context.stream(index))
+                    .and_then(|stream| count_frames(&stream, container_duration))
+            } else {
+                None
+            };
+
+            let efficiency = if args.efficiency {
+                best_video
+                    .and_then(|index| context.stream(index))
+                    .and_then(|stream| {
+                        // Containers rarely store a per stream bit rate, fall
+                        // back to the bit rate of the whole file
+                        let bit_rate = if stream_bit_rate(&stream) > 0 {
+                            stream_bit_rate(&stream)
+                        } else {
+                            context.bit_rate()
+                        };
+                        bits_per_pixel(
+                            bit_rate,
+                            stream_resolution(&stream.parameters())?,
+                            f64::from(frame_rate(&stream)?),
+                        )
+                    })
+            } else {
+                None
+            };
+
+            let start_spread = if args.start_spread {
+                start_spread(&context)
+            } else {
+                None
+            };
+
+            let packets = if args.decode {
+                let mut packets = PacketReport {
+                    first_keyframe: profile::time(Phase::Decode, || {
+                        best_video.and_then(|index| find_first_keyframe(&mut context, index))
+                    }),
+                    captions: profile::time(Phase::Decode, || {
+                        best_video
+                            .and_then(|index| detect_captions(&mut context, index, CAPTION_FRAMES))
+                    }),
+                    cadence: if args.keyframe_cadence {
+                        profile::time(Phase::Decode, || {
+                            best_video
+                                .map(|index| keyframe_times(&mut context, index, CADENCE_WINDOW))
+                                .and_then(|times| keyframe_cadence(&times))
+                        })
+                    } else {
+                        None
+                    },
+                    interleave: None,
+                    frame_sizes: None,
+                };
+
+                // Both need every packet of the file, so read them in one pass
+                let scan = profile::time(Phase::Decode, || {
+                    scan_packets(
+                        &mut context,
+                        best_video.filter(|_| args.frame_sizes),
+                        best_video.zip(best_audio).filter(|_| args.interleave),
+                    )
+                });
+                packets.interleave = scan.max_interleave_gap.map(|max_gap| Interleave {
+                    max_gap,
+                    good: max_gap <= args.max_interleave_gap,
+                });
+                packets.frame_sizes = frame_sizes(&scan.video_sizes);
+
+                Some(packets)
+            } else {
+                None
+            };
+
+            let mut markers = Vec::new();
+            if let (Some(score), Some(min)) = (probe_score, args.min_probe_score) {
+                if score < min {
+                    markers.push(Marker {
+                        label: "LOW-PROBE-SCORE",
+                        reason: format!(
+                            "ffmpeg is only {}/100 sure the file is {} (minimum {})",
+                            score,
+                            context.format().name(),
+                            min
+                        ),
+                    });
+                }
+            }
+            if let Some(frames) = args.blank_frames {
+                let spread = profile::time(Phase::Decode, || {
+                    best_video.and_then(|index| measure_luma_spread(&mut context, index, frames))
+                });
+                if let Some(spread) = spread {
+                    if spread <= args.blank_threshold {
+                        markers.push(Marker {
+                            label: "BLANK-VIDEO",
+                            reason: format!(
+                                "brightness varies by at most {} levels over the first {} frames (threshold {})",
+                                spread, frames, args.blank_threshold
+                            ),
+                        });
+                    }
+                }
+            }
+
+            if let Some(cadence) = packets
+                .as_ref()
+                .and_then(|packets| packets.cadence.as_ref())
+            {
+                if !cadence.regular {
+                    markers.push(Marker {
+                        label: "IRREGULAR-KEYFRAMES",
+                        reason: format!(
+                            "keyframes are {:.2}s to {:.2}s apart, segments can't all be cut at the same length",
+                            cadence.min, cadence.max
+                        ),
+                    });
+                }
+            }
+
+            if let Some(interleave) = packets
+                .as_ref()
+                .and_then(|packets| packets.interleave.as_ref())
+            {
+                if !interleave.good {
+                    markers.push(Marker {
+                        label: "POOR-INTERLEAVE",
+                        reason: format!(
+                            "audio and video packets drift up to {:.2}s apart (maximum {:.2}s)",
+                            interleave.max_gap, args.max_interleave_gap
+                        ),
+                    });
+                }
+            }
+
+            if let Some(frame_count) = &frame_count {
+                if frame_count_diverges(frame_count, args.frame_count_tolerance) {
+                    markers.push(Marker {
+                        label: "FRAME-COUNT-MISMATCH",
+                        reason: format!(
+                            "{} frames declared but duration and frame rate imply {}",
+                            frame_count.declared, frame_count.estimated
+                        ),
+                    });
+                }
+            }
+
+            let sample_aspect = if args.check_aspect {
+                best_video
+                    .and_then(|index| context.stream(index))
+                    .and_then(|stream| {
+                        // Not exposed by ffmpeg-next, read straight from
+                        // AVStream and AVCodecParameters
+                        let container = unsafe { (*stream.as_ptr()).sample_aspect_ratio };
+                        let codec = unsafe { (*stream.parameters().as_ptr()).sample_aspect_ratio };
+                        if sar_conflict(container, codec) {
+                            Some(SampleAspect {
+                                container: (container.num, container.den),
+                                codec: (codec.num, codec.den),
+                            })
+                        } else {
+                            None
+                        }
+                    })
+            } else {
+                None
+            };
+            if let Some(aspect) = &sample_aspect {
+                markers.push(Marker {
+                    label: "SAR-CONFLICT",
+                    reason: aspect.describe(),
+                });
+            }
+
+            if let Some(efficiency) = efficiency {
+                let outside = match (args.efficiency_min, args.efficiency_max) {
+                    (Some(min), _) if efficiency < min => Some(("below", min)),
+                    (_, Some(max)) if efficiency > max => Some(("above", max)),
+                    _ => None,
+                };
+                if let Some((direction, bound)) = outside {
+                    markers.push(Marker {
+                        label: "BITRATE-OUTLIER",
+                        reason: format!(
+                            "{:.3} bits per pixel is {} the configured {:.3}",
+                            efficiency, direction, bound
+                        ),
+                    });
+                }
+            }
+
+            if let Some(spread) = start_spread {
+                if spread > args.max_start_spread {
+                    markers.push(Marker {
+                        label: "START-SPREAD",
+                        reason: format!(
+                            "the audio and video streams start {:.3}s apart (maximum {:.3}s)",
+                            spread, args.max_start_spread
+                        ),
+                    });
+                }
+            }
+
+            // Unknown durations are AV_NOPTS_VALUE, a large negative number
+            let duration = u64::try_from(context.duration())
+                .ok()
+                .map(Duration::from_micros);
+
+            let category = if args.categorize {
+                Some(categorize(
+                    &streams,
+                    duration,
+                    Duration::from_secs(args.movie_minutes * 60),
+                    Duration::from_secs(args.clip_minutes * 60),
+                ))
+            } else {
+                None
+            };
+
+            // Durations from the stream headers are exact, anything estimated
+            // while sampling only reflects the part of the file that was read
+            let duration_sampled = args.sample_seconds.is_some()
+                && unsafe { (*context.as_ptr()).duration_estimation_method }
+                    != ffi::AVDurationEstimationMethod::AVFMT_DURATION_FROM_STREAM;
+
+            Ok(MediaReport {
+                // Filename + path from the root
+                path: path.clone(),
+                format_name: context.format().name().to_owned(),
+                format_extensions: context
+                    .format()
+                    .extensions()
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                format_flags,
+                muxer,
+                probe_score,
+                duration,
+                duration_sampled,
+                bit_rate: context.bit_rate(),
+                size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                streams,
+                editions,
+                best_video,
+                best_audio,
+                color,
+                packets,
+                frame_count,
+                efficiency,
+                start_spread,
+                category,
+                sample_aspect,
+                markers,
+                error: None,
+            })
+        }
+        Err(error) => Err(ReportError {
+            path: path.clone(),
+            reason: error.to_string(),
+        }),
+    }
+}
+
+/// Guess the kind of content from the streams and the duration. Audio only
+/// files are music whatever their length, videos of at least `movie` are
+/// movies, shorter than `clip` are clips and anything in between an episode
+fn categorize(
+    streams: &[StreamReport],
+    duration: Option<Duration>,
+    movie: Duration,
+    clip: Duration,
+) -> Category {
+    let has_video = streams
+        .iter()
+        .any(|stream| stream.kind == StreamKind::Video && !stream.attached_picture);
+    let has_audio = streams
+        .iter()
+        .any(|stream| stream.kind == StreamKind::Audio);
+
+    if has_audio && !has_video {
+        return Category::Music;
+    }
+
+    match duration {
+        _ if !has_video => Category::Unknown,
+        Some(duration) if duration >= movie => Category::Movie,
+        Some(duration) if duration < clip => Category::Clip,
+        Some(_) => Category::Episode,
+        None => Category::Unknown,
+    }
+}
+
+/// Labels of the capabilities of an input, from whether its I/O can seek,
+/// the `AVFMTCTX_*` flags of its context and the `AVFMT_*` flags of its format
+fn input_format_flags(
+    io_seekable: bool,
+    context_flags: i32,
+    input_flags: i32,
+) -> Vec<&'static str> {
+    let live = context_flags & ffi::AVFMTCTX_UNSEEKABLE != 0;
+    [
+        (io_seekable && !live, "seekable"),
+        (live, "live"),
+        (input_flags & ffi::AVFMT_NOTIMESTAMPS != 0, "no-timestamps"),
+        (
+            input_flags & ffi::AVFMT_TS_DISCONT != 0,
+            "discontinuous-timestamps",
+        ),
+        (input_flags & ffi::AVFMT_NO_BYTE_SEEK != 0, "no-byte-seek"),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, label)| label)
+    .collect()
+}
+
+/// Seconds between the earliest and the latest start time of the audio and
+/// video streams. Cover art and streams without a start time are left out,
+/// and at least two streams have to remain
+fn start_spread(context: &ffmpeg::format::context::Input) -> Option<f64> {
+    let starts: Vec<f64> = context
+        .streams()
+        .filter(|stream| {
+            matches!(
+                stream.parameters().medium(),
+                media::Type::Video | media::Type::Audio
+            ) && !is_attached_picture(stream)
+        })
+        .filter(|stream| stream.start_time() != ffi::AV_NOPTS_VALUE)
+        .map(|stream| stream.start_time() as f64 * f64::from(stream.time_base()))
+        .collect();
+
+    spread(&starts)
+}
+
+/// Largest minus smallest value, none for fewer than two values
+fn spread(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    Some(max - min)
+}
+
+/// Index of the stream of `kind` that represents the file, highest `score`
+/// first with ties going to the earlier stream. Without a score ffmpeg's own
+/// pick is used. Cover art never represents the video of a file
+fn best_stream(
+    context: &ffmpeg::format::context::Input,
+    kind: media::Type,
+    score: Option<fn(&ffmpeg::Stream) -> i64>,
+) -> Option<usize> {
+    let score = match score {
+        Some(score) => score,
+        None => {
+            return context
+                .streams()
+                .best(kind)
+                .filter(|stream| !is_attached_picture(stream))
+                .map(|stream| stream.index())
+        }
+    };
+
+    let mut best: Option<(usize, i64)> = None;
+    for stream in context.streams() {
+        if stream.parameters().medium() != kind || is_attached_picture(&stream) {
+            continue;
+        }
+
+        let value = score(&stream);
+        match best {
+            Some((_, best_value)) if best_value >= value => {}
+            _ => best = Some((stream.index(), value)),
+        }
+    }
+
+    best.map(|(index, _)| index)
+}
+
+fn is_attached_picture(stream: &ffmpeg::Stream) -> bool {
+    stream
+        .disposition()
+        .contains(format::stream::Disposition::ATTACHED_PIC)
+}
+
+/// Bit rate the container declares for a stream, 0 when unknown
+fn stream_bit_rate(stream: &ffmpeg::Stream) -> i64 {
+    unsafe { (*stream.parameters().as_ptr()).bit_rate }
+}
+
+/// Demuxer options limiting how much of a file is read while probing
+fn sampling_options<'a>(seconds: u64) -> Dictionary<'a> {
+    let mut options = Dictionary::new();
+    options.set(
+        "analyzeduration",
+        &seconds.saturating_mul(1_000_000).to_string(),
+    );
+    // Don't read the end of the file to refine the duration from timestamps
+    options.set("skip_estimate_duration_from_pts", "1");
+    options
+}
+
+/// Container tags naming the muxing application, in order of preference.
+/// Matroska and MP4 (`©too`) files written by ffmpeg both use `encoder`
+const MUXER_TAGS: [&str; 3] = ["writing_app", "muxing_app", "encoder"];
+
+/// Application that muxed the file, according to the container tags
+fn container_muxer(metadata: &ffmpeg::DictionaryRef, normalize: bool) -> Option<String> {
+    let muxer = MUXER_TAGS
+        .iter()
+        .find_map(|tag| metadata.get(tag))
+        .map(str::trim)
+        .filter(|muxer| !muxer.is_empty())?;
+
+    Some(if normalize {
+        strip_versions(muxer)
+    } else {
+        muxer.to_owned()
+    })
+}
+
+/// Number of editions (theatrical, extended, ...) from the `edition` tags
+/// of the chapters. ffmpeg flattens the chapters of a Matroska file into a
+/// single list without its edition entries, the tags are what is left to
+/// tell them apart
+fn count_editions(tags: impl Iterator<Item = String>) -> usize {
+    tags.map(|tag| tag.trim().to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Remove version numbers from a tool name, both standalone (`v1.4.2`,
+/// `58.29.100`) and glued to the name (`Lavf58.29.100` becomes `Lavf`)
+fn strip_versions(text: &str) -> String {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .filter_map(|word| {
+            let name = word.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+            // A lone `v` was the prefix of a version number, while digits
+            // glued to a name without any dot belong to it, as in `x264`
+            match name {
+                "" | "v" | "V" => None,
+                name if word[name.len()..].contains('.') => Some(name),
+                _ => Some(word),
+            }
+        })
+        .collect();
+
+    words.join(" ")
+}
+
+/// Describe a single stream of the file
+fn analyze_stream(stream: &ffmpeg::Stream, args: &Options) -> StreamReport {
+    let parameters = stream.parameters();
+    let metadata = stream.metadata();
+
+    StreamReport {
+        kind: stream_kind(parameters.medium()),
+        codec: parameters.id().name().to_owned(),
+        codec_long_name: if args.codec_long_name {
+            codec_long_name(parameters.id())
+        } else {
+            None
+        },
+        attached_picture: is_attached_picture(stream),
+        resolution: stream_resolution(&parameters),
+        // `und` is what muxers write when the language isn't known
+        language: metadata
+            .get("language")
+            .filter(|language| *language != "und")
+            .map(String::from),
+        title: metadata.get("title").map(String::from),
+    }
+}
+
+/// Width and height of a video stream, when the container declares them
+fn stream_resolution(parameters: &codec::Parameters) -> Option<(u32, u32)> {
+    if parameters.medium() != media::Type::Video {
+        return None;
+    }
+
+    // Not exposed by ffmpeg-next without opening a decoder
+    let raw = unsafe { &*parameters.as_ptr() };
+    match (u32::try_from(raw.width), u32::try_from(raw.height)) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// Model equivalent of an ffmpeg media type
+fn stream_kind(medium: media::Type) -> StreamKind {
+    match medium {
+        media::Type::Video => StreamKind::Video,
+        media::Type::Audio => StreamKind::Audio,
+        media::Type::Subtitle => StreamKind::Subtitle,
+        media::Type::Data => StreamKind::Data,
+        media::Type::Attachment => StreamKind::Attachment,
+        media::Type::Unknown => StreamKind::Unknown,
+    }
+}
+
+/// Long name of a codec from its descriptor, e.g.
+/// `H.265 / HEVC (High Efficiency Video Coding)`
+fn codec_long_name(id: codec::Id) -> Option<String> {
+    let descriptor = unsafe { ffi::avcodec_descriptor_get(id.into()).as_ref()? };
+
+    if descriptor.long_name.is_null() {
+        None
+    } else {
+        let long_name = unsafe { CStr::from_ptr(descriptor.long_name) };
+        Some(long_name.to_string_lossy().into_owned())
+    }
+}
+
+/// Declared and estimated frame count of a video stream. Returns `None` when
+/// the stream doesn't declare a frame count or has no usable frame rate or
+/// duration
+fn count_frames(stream: &ffmpeg::Stream, container_duration: Option<f64>) -> Option<FrameCount> {
+    let declared = u64::try_from(stream.frames())
+        .ok()
+        .filter(|&frames| frames > 0)?;
+
+    let frame_rate = frame_rate(stream)?;
+
+    let duration = if stream.duration() > 0 {
+        stream.duration() as f64 * f64::from(stream.time_base())
+    } else {
+        container_duration?
+    };
+
+    Some(FrameCount {
+        declared,
+        estimated: (duration * f64::from(frame_rate)).round() as u64,
+    })
+}
+
+/// Average frame rate of a stream, falling back to the base rate for
+/// containers that don't declare an average
+fn frame_rate(stream: &ffmpeg::Stream) -> Option<ffmpeg::Rational> {
+    let valid = |rate: &ffmpeg::Rational| rate.numerator() > 0 && rate.denominator() > 0;
+    Some(stream.avg_frame_rate())
+        .filter(valid)
+        .or_else(|| Some(stream.rate()).filter(valid))
+}
+
+/// Bits per pixel of a video stream, given its bit rate in bits per second
+fn bits_per_pixel(bit_rate: i64, (width, height): (u32, u32), frame_rate: f64) -> Option<f64> {
+    let pixels_per_second = f64::from(width) * f64::from(height) * frame_rate;
+    if bit_rate <= 0 || pixels_per_second <= 0.0 {
+        None
+    } else {
+        Some(bit_rate as f64 / pixels_per_second)
+    }
+}
+
+/// The declared count is off by more than `tolerance` percent of the
+/// estimate, and always by at least a whole frame to absorb rounding
+fn frame_count_diverges(frame_count: &FrameCount, tolerance: f64) -> bool {
+    let allowed = (frame_count.estimated as f64 * tolerance / 100.0).max(1.0);
+    (frame_count.declared as f64 - frame_count.estimated as f64).abs() > allowed
+}
+
+/// Both sample aspect ratios are known and describe a different ratio.
+/// `0:1` stands for an unknown ratio
+fn sar_conflict(container: ffi::AVRational, codec: ffi::AVRational) -> bool {
+    let known = |sar: &ffi::AVRational| sar.num > 0 && sar.den > 0;
+    known(&container)
+        && known(&codec)
+        && i64::from(container.num) * i64::from(codec.den)
+            != i64::from(codec.num) * i64::from(container.den)
+}
+
+/// Collect the raw sample bit depth and any HDR10 mastering display /
+/// content light level side data of a video stream
+fn collect_color_info(stream: &ffmpeg::Stream) -> ColorInfo {
+    let mut color = ColorInfo::default();
+
+    // Not exposed by ffmpeg-next, read straight from AVCodecParameters
+    let bits_per_raw_sample = unsafe { (*stream.parameters().as_ptr()).bits_per_raw_sample };
+    if bits_per_raw_sample > 0 {
+        color.bits_per_raw_sample = Some(bits_per_raw_sample);
+    }
+
+    for side_data in stream.side_data() {
+        match side_data.kind() {
+            side_data::Type::MasteringDisplayMetadata => {
+                color.mastering_display = parse_mastering_display(side_data.data());
+            }
+            side_data::Type::ContentLightLevel => {
+                color.content_light_level = parse_content_light_level(side_data.data());
+            }
+            _ => {}
+        }
+    }
+
+    color
+}
+
+/// Read the packets of video stream `index` until the first keyframe is
+/// found. Returns `None` when the stream contains no keyframe at all
+fn find_first_keyframe(
+    context: &mut ffmpeg::format::context::Input,
+    index: usize,
+) -> Option<FirstKeyframe> {
+    let time_base = f64::from(context.stream(index)?.time_base());
+
+    let mut first_timestamp = None;
+    let mut packet_count = 0;
+    for (stream, packet) in context.packets() {
+        if stream.index() != index {
+            continue;
+        }
+
+        packet_count += 1;
+        let timestamp = packet.dts().or_else(|| packet.pts());
+        if packet_count == 1 {
+            first_timestamp = timestamp;
+        }
+
+        if packet.is_key() {
+            let offset = match (first_timestamp, timestamp) {
+                (Some(first), Some(timestamp)) => (timestamp - first) as f64 * time_base,
+                _ => 0.0,
+            };
+
+            return Some(FirstKeyframe {
+                offset,
+                clean_start: packet_count == 1,
+            });
+        }
+    }
+
+    None
+}
+
+/// Decode up to `frames` frames from the start of video stream `index` and
+/// return the largest difference between the darkest and brightest pixel
+/// seen in any of them. Returns `None` when nothing could be decoded
+fn measure_luma_spread(
+    context: &mut ffmpeg::format::context::Input,
+    index: usize,
+    frames: usize,
+) -> Option<u8> {
+    let mut spreads = Vec::new();
+    decode_video_frames(context, index, frames, |decoded| {
+        if let Some(spread) = frame_luma_spread(decoded) {
+            spreads.push(spread);
+        }
+    })?;
+
+    spreads.into_iter().max()
+}
+
+/// Seconds from the start of the video stream over which the keyframe
+/// spacing is measured
+const CADENCE_WINDOW: f64 = 120.0;
+
+/// Largest deviation from the median keyframe distance, as a fraction of it,
+/// for which the keyframes still count as evenly spaced
+const CADENCE_TOLERANCE: f64 = 0.1;
+
+/// Presentation times, in seconds, of the keyframes of video stream `index`
+/// within its first `window` seconds
+fn keyframe_times(
+    context: &mut ffmpeg::format::context::Input,
+    index: usize,
+    window: f64,
+) -> Vec<f64> {
+    let time_base = match context.stream(index) {
+        Some(stream) => f64::from(stream.time_base()),
+        None => return Vec::new(),
+    };
+    // Earlier checks may already have read past the start of the file
+    if context.seek(0, ..).is_err() {
+        return Vec::new();
+    }
+
+    let mut times = Vec::new();
+    for (stream, packet) in context.packets() {
+        if stream.index() != index {
+            continue;
+        }
+
+        let time = match packet.pts().or_else(|| packet.dts()) {
+            Some(timestamp) => timestamp as f64 * time_base,
+            None => continue,
+        };
+        match times.first() {
+            Some(first) if time - first > window => break,
+            _ => {}
+        }
+        if packet.is_key() {
+            times.push(time);
+        }
+    }
+
+    times
+}
+
+/// What the checks reading every packet of the file collected
+#[derive(Debug, Default)]
+struct PacketScan {
+    /// Size of every packet of the video stream, for `--frame-sizes`
+    video_sizes: Vec<u64>,
+    /// See `max_interleave_gap`, for `--interleave`
+    max_interleave_gap: Option<f64>,
+}
+
+/// Read every packet of the file once, from the start, collecting the sizes
+/// of the `sizes_of` stream and the largest gap between the timestamps of
+/// the `interleave_of` video and audio streams. Nothing is read when
+/// neither is asked for
+fn scan_packets(
+    context: &mut ffmpeg::format::context::Input,
+    sizes_of: Option<usize>,
+    interleave_of: Option<(usize, usize)>,
+) -> PacketScan {
+    let mut scan = PacketScan::default();
+    let time_bases = interleave_of.and_then(|(video, audio)| {
+        Some((
+            f64::from(context.stream(video)?.time_base()),
+            f64::from(context.stream(audio)?.time_base()),
+        ))
+    });
+    // Earlier checks may already have read past the start of the file
+    if (sizes_of.is_none() && time_bases.is_none()) || context.seek(0, ..).is_err() {
+        return scan;
+    }
+
+    let video_sizes = &mut scan.video_sizes;
+    let max_gap = max_interleave_gap(context.packets().filter_map(|(stream, packet)| {
+        let index = stream.index();
+        if sizes_of == Some(index) {
+            video_sizes.push(packet.size() as u64);
+        }
+
+        let ((video, audio), time_bases) = interleave_of.zip(time_bases)?;
+        let timestamp = packet.dts().or_else(|| packet.pts())?;
+        match index {
+            index if index == video => Some((true, timestamp as f64 * time_bases.0)),
+            index if index == audio => Some((false, timestamp as f64 * time_bases.1)),
+            _ => None,
+        }
+    }));
+    scan.max_interleave_gap = max_gap;
+
+    scan
+}
+
+/// Largest distance between the latest video and the latest audio time seen
+/// at each of the `(is_video, time)` packets in file order. None unless both
+/// streams have packets
+fn max_interleave_gap(packets: impl IntoIterator<Item = (bool, f64)>) -> Option<f64> {
+    let (mut video, mut audio) = (None, None);
+    let mut max_gap: Option<f64> = None;
+    for (is_video, time) in packets {
+        if is_video {
+            video = Some(time);
+        } else {
+            audio = Some(time);
+        }
+        if let (Some(video), Some(audio)) = (video, audio) {
+            let gap = (video - audio).abs();
+            max_gap = Some(max_gap.map_or(gap, |max_gap| max_gap.max(gap)));
+        }
+    }
+
+    max_gap
+}
+
+/// Smallest, largest and average of the frame sizes, none without frames
+fn frame_sizes(sizes: &[u64]) -> Option<FrameSizes> {
+    Some(FrameSizes {
+        min: *sizes.iter().min()?,
+        max: *sizes.iter().max()?,
+        average: sizes.iter().sum::<u64>() / sizes.len() as u64,
+    })
+}
+
+/// Spacing of keyframes at the given times. Needs at least three keyframes,
+/// as a single distance doesn't say anything about the regularity
+fn keyframe_cadence(times: &[f64]) -> Option<KeyframeCadence> {
+    let mut intervals: Vec<f64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if intervals.len() < 2 {
+        return None;
+    }
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let interval = intervals[intervals.len() / 2];
+    let (min, max) = (intervals[0], intervals[intervals.len() - 1]);
+    let tolerance = interval * CADENCE_TOLERANCE;
+
+    Some(KeyframeCadence {
+        interval,
+        min,
+        max,
+        regular: interval > 0.0 && interval - min <= tolerance && max - interval <= tolerance,
+    })
+}
+
+/// Video frames decoded while looking for embedded captions, about five
+/// seconds. Broadcast captures carry caption data in nearly every frame
+const CAPTION_FRAMES: usize = 150;
+
+/// Decode up to `frames` frames from the start of video stream `index`,
+/// looking for CEA-608/708 captions in their side data. Returns `None` when
+/// none of the frames carried captions
+fn detect_captions(
+    context: &mut ffmpeg::format::context::Input,
+    index: usize,
+    frames: usize,
+) -> Option<Captions> {
+    let mut captions = Captions::default();
+    decode_video_frames(context, index, frames, |decoded| {
+        if let Some(side_data) = decoded.side_data(frame::side_data::Type::A53CC) {
+            let found = caption_services(side_data.data());
+            captions.cea_608 |= found.cea_608;
+            captions.cea_708 |= found.cea_708;
+        }
+    })?;
+
+    Some(captions).filter(|captions| captions.cea_608 || captions.cea_708)
+}
+
+/// Services present in an A53 `cc_data` payload, a list of three byte
+/// constructs with the validity and type in the low bits of the first byte.
+/// Types 0 and 1 are the two CEA-608 fields, 2 and 3 CEA-708 packet data
+fn caption_services(data: &[u8]) -> Captions {
+    let mut captions = Captions::default();
+    for construct in data.chunks_exact(3) {
+        let valid = construct[0] & 0x04 != 0;
+        // CEA-608 pairs without the parity bit are padding, not captions
+        let padding = construct[1] & 0x7f == 0 && construct[2] & 0x7f == 0;
+        match construct[0] & 0x03 {
+            0 | 1 if valid && !padding => captions.cea_608 = true,
+            2 | 3 if valid => captions.cea_708 = true,
+            _ => {}
+        }
+    }
+    captions
+}
+
+/// Decode up to `frames` frames from the start of video stream `index`,
+/// handing each to `on_frame`. Returns `None` when the stream can't be decoded
+fn decode_video_frames(
+    context: &mut ffmpeg::format::context::Input,
+    index: usize,
+    frames: usize,
+    mut on_frame: impl FnMut(&frame::Video),
+) -> Option<()> {
+    let parameters = context.stream(index)?.parameters();
+    let mut decoder = codec::context::Context::from_parameters(parameters)
+        .ok()?
+        .decoder()
+        .video()
+        .ok()?;
+
+    // Earlier checks may already have read past the start of the file
+    context.seek(0, ..).ok()?;
+
+    let mut decoded = frame::Video::empty();
+    let mut count = 0;
+    for (stream, packet) in context.packets() {
+        if stream.index() != index {
+            continue;
+        }
+
+        if decoder.send_packet(&packet).is_ok() {
+            while count < frames && decoder.receive_frame(&mut decoded).is_ok() {
+                on_frame(&decoded);
+                count += 1;
+            }
+        }
+        if count >= frames {
+            return Some(());
+        }
+    }
+
+    if decoder.send_eof().is_ok() {
+        while count < frames && decoder.receive_frame(&mut decoded).is_ok() {
+            on_frame(&decoded);
+            count += 1;
+        }
+    }
+
+    Some(())
+}
+
+/// Luma spread of a decoded frame, after converting it to 8 bit gray
+fn frame_luma_spread(decoded: &frame::Video) -> Option<u8> {
+    let mut gray = frame::Video::empty();
+    scaling::Context::get(
+        decoded.format(),
+        decoded.width(),
+        decoded.height(),
+        format::Pixel::GRAY8,
+        decoded.width(),
+        decoded.height(),
+        scaling::Flags::POINT,
+    )
+    .and_then(|mut scaler| scaler.run(decoded, &mut gray))
+    .ok()?;
+
+    Some(luma_spread(
+        gray.data(0),
+        gray.stride(0),
+        gray.width() as usize,
+        gray.height() as usize,
+    ))
+}
+
+/// Difference between the darkest and brightest pixel of an 8 bit plane
+fn luma_spread(data: &[u8], stride: usize, width: usize, height: usize) -> u8 {
+    let pixels = data
+        .chunks(stride.max(1))
+        .take(height)
+        .flat_map(|row| &row[..width.min(row.len())]);
+
+    let (min, max) = pixels.fold((u8::MAX, u8::MIN), |(min, max), &pixel| {
+        (min.min(pixel), max.max(pixel))
+    });
+    max.saturating_sub(min)
+}
+
+/// Read the `index`th native endian i32 out of a side data buffer
+fn read_i32(data: &[u8], index: usize) -> Option<i32> {
+    let bytes = data.get(index * 4..index * 4 + 4)?;
+    Some(i32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// Read the `index`th AVRational out of a side data buffer as a float
+fn read_rational(data: &[u8], index: usize) -> Option<f64> {
+    let numerator = read_i32(data, index * 2)?;
+    let denominator = read_i32(data, index * 2 + 1)?;
+
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator as f64 / denominator as f64)
+    }
+}
+
+/// Decode an AVMasteringDisplayMetadata side data buffer, laid out as
+/// `display_primaries[3][2]`, `white_point[2]`, `min_luminance` and
+/// `max_luminance` rationals followed by the `has_primaries` and
+/// `has_luminance` ints
+fn parse_mastering_display(data: &[u8]) -> Option<String> {
+    let has_primaries = read_i32(data, 20)? != 0;
+    let has_luminance = read_i32(data, 21)? != 0;
+    let mut parts = Vec::new();
+
+    if has_primaries {
+        for (index, label) in ["R", "G", "B", "WP"].iter().enumerate() {
+            parts.push(format!(
+                "{}({:.4},{:.4})",
+                label,
+                read_rational(data, index * 2)?,
+                read_rational(data, index * 2 + 1)?
+            ));
+        }
+    }
+
+    if has_luminance {
+        parts.push(format!(
+            "L({:.4},{:.4})",
+            read_rational(data, 8)?,
+            read_rational(data, 9)?
+        ));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Decode an AVContentLightMetadata side data buffer, two unsigned ints
+/// holding MaxCLL and MaxFALL
+fn parse_content_light_level(data: &[u8]) -> Option<String> {
+    Some(format!(
+        "MaxCLL {}, MaxFALL {}",
+        read_i32(data, 0)? as u32,
+        read_i32(data, 1)? as u32
+    ))
+}
+
+/// Sort discovered entries into the requested scan order. Ties, and entries
+/// whose metadata can't be read, fall back to alphabetical order
+fn sort_entries(entries: &mut [DirEntry], scan_order: &ScanOrder) {
+    match scan_order {
+        ScanOrder::Alpha => entries.sort_by(|a, b| a.path().cmp(b.path())),
+        ScanOrder::Mtime => entries.sort_by_cached_key(|entry| {
+            let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+            (Reverse(modified), entry.path().to_path_buf())
+        }),
+        ScanOrder::Size => entries.sort_by_cached_key(|entry| {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            (Reverse(size), entry.path().to_path_buf())
+        }),
+    }
+}
+
+/// Validates if a given DirEntry should be used for diff purposes
+/// This is a simple filter, for non-file entries and .nfo files. As needs
+/// evolve more cases should be included
+fn should_inspect_file(entry: &DirEntry) -> bool {
+    !entry.file_type().is_dir() && !entry.file_name().to_str().unwrap().ends_with(".nfo")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rationals_to_bytes(values: &[(i32, i32)], flags: &[i32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|(num, den)| [*num, *den])
+            .chain(flags.iter().copied())
+            .flat_map(i32::to_ne_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn test_mastering_display() {
+        let data = rationals_to_bytes(
+            &[
+                (34000, 50000),
+                (16000, 50000),
+                (13250, 50000),
+                (34500, 50000),
+                (7500, 50000),
+                (3000, 50000),
+                (15635, 50000),
+                (16450, 50000),
+                (50, 10000),
+                (10000000, 10000),
+            ],
+            &[1, 1],
+        );
+
+        assert_eq!(
+            parse_mastering_display(&data).unwrap(),
+            "R(0.6800,0.3200) G(0.2650,0.6900) B(0.1500,0.0600) WP(0.3127,0.3290) L(0.0050,1000.0000)"
+        );
+    }
+
+    #[test]
+    fn test_mastering_display_luminance_only() {
+        let data = rationals_to_bytes(&[(0, 1); 8], &[]);
+        let data = [data, rationals_to_bytes(&[(1, 10000), (4000, 1)], &[0, 1])].concat();
+
+        assert_eq!(
+            parse_mastering_display(&data).unwrap(),
+            "L(0.0001,4000.0000)"
+        );
+    }
+
+    #[test]
+    fn test_mastering_display_truncated() {
+        assert_eq!(parse_mastering_display(&[0; 40]), None);
+    }
+
+    #[test]
+    fn test_content_light_level() {
+        let data = rationals_to_bytes(&[(1000, 400)], &[]);
+        assert_eq!(
+            parse_content_light_level(&data).unwrap(),
+            "MaxCLL 1000, MaxFALL 400"
+        );
+    }
+
+    #[test]
+    fn test_luma_spread() {
+        // 2x2 frame with a stride of 4, the padding bytes must be ignored
+        let black = [16, 17, 255, 255, 16, 18, 0, 0];
+        assert_eq!(luma_spread(&black, 4, 2, 2), 2);
+
+        let content = [16, 235, 0, 0, 80, 120, 0, 0];
+        assert_eq!(luma_spread(&content, 4, 2, 2), 219);
+
+        assert_eq!(luma_spread(&[], 0, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_keyframe_cadence() {
+        let regular = keyframe_cadence(&[0.0, 2.0, 4.02, 6.0, 8.0]).unwrap();
+        assert!(regular.regular);
+        assert_eq!(regular.interval, 2.0);
+
+        // A scene cut keyframe in between breaks the fixed length segments
+        let irregular = keyframe_cadence(&[0.0, 2.0, 2.8, 4.0, 6.0]).unwrap();
+        assert!(!irregular.regular);
+        assert!((irregular.min - 0.8).abs() < 1e-9);
+        assert_eq!(irregular.max, 2.0);
+
+        assert_eq!(keyframe_cadence(&[0.0, 2.0]), None);
+    }
+
+    #[test]
+    fn test_caption_services() {
+        let cea_608 = [0xfc, 0x94, 0x20, 0xfd, 0x80, 0x80];
+        assert_eq!(
+            caption_services(&cea_608),
+            Captions {
+                cea_608: true,
+                cea_708: false
+            }
+        );
+
+        let cea_708 = [0xfc, 0x80, 0x80, 0xff, 0x02, 0x21, 0xfe, 0x00, 0x00];
+        assert_eq!(
+            caption_services(&cea_708),
+            Captions {
+                cea_608: false,
+                cea_708: true
+            }
+        );
+
+        // Invalid constructs are skipped whatever they carry
+        assert_eq!(caption_services(&[0xf8, 0x94, 0x20]), Captions::default());
+        assert_eq!(caption_services(&[]), Captions::default());
+    }
+
+    #[test]
+    fn test_options_new() {
+        let options = Options::new("/media");
+        assert_eq!(options.root_dir, PathBuf::from("/media"));
+        assert!(!options.decode);
+        assert_eq!(options.max_start_spread, 0.1);
+    }
+
+    #[test]
+    fn test_check() {
+        let check = |args: &[&str]| {
+            let command_line = ["media-server-diff", "--root-dir", "/media"];
+            Options::parse_from(command_line.iter().chain(args)).check()
+        };
+        assert!(check(&["--format", "csv", "--columns", "path,size"]).is_ok());
+        assert!(check(&["--columns", "path,size"]).is_err());
+        assert!(check(&["--group-by-codec"]).is_ok());
+        assert!(check(&["--format", "json", "--group-by-codec"]).is_err());
+        assert!(check(&["--output", "report.txt", "--split-size", "10MB"]).is_ok());
+        assert!(check(&["--output", "-", "--split-size", "10MB"]).is_err());
+    }
+
+    #[test]
+    fn test_keeps_failed() {
+        let options = |args: &[&str]| {
+            let command_line = ["media-server-diff", "--root-dir", "/media"];
+            Options::parse_from(command_line.iter().chain(args))
+        };
+        assert!(!keeps_failed(&options(&["--preserve-ffmpeg-errors"])));
+        assert!(keeps_failed(&options(&[
+            "--preserve-ffmpeg-errors",
+            "--format",
+            "json"
+        ])));
+        assert!(keeps_failed(&options(&["--treat-errors-as-entries"])));
+    }
+
+    #[test]
+    fn test_in_scan_order() {
+        let (sender, receiver) = mpsc::sync_channel(8);
+        // Index 3 never arrives, as if its worker had panicked
+        for index in [2, 0, 4, 1] {
+            sender.send((index, index * 10)).unwrap();
+        }
+        drop(sender);
+
+        let ordered = InScanOrder {
+            receiver,
+            pending: BTreeMap::new(),
+            next: 0,
+            window: Arc::new(ScanWindow::new()),
+        };
+        assert_eq!(ordered.collect::<Vec<_>>(), vec![0, 10, 20, 40]);
+    }
+
+    #[test]
+    fn test_scan_window() {
+        let window = ScanWindow::new();
+        assert!(window.enter(REORDER_WINDOW - 1));
+
+        window.advance(Some(1));
+        assert!(window.enter(REORDER_WINDOW));
+
+        // Dropping the iterator releases the workers instead of blocking them
+        window.advance(None);
+        assert!(!window.enter(REORDER_WINDOW + 1));
+    }
+
+    #[test]
+    fn test_categorize() {
+        let stream = |kind, attached_picture| StreamReport {
+            kind,
+            codec: String::from("codec"),
+            attached_picture,
+            ..StreamReport::default()
+        };
+        let video = [
+            stream(StreamKind::Video, false),
+            stream(StreamKind::Audio, false),
+        ];
+        let movie = Duration::from_secs(70 * 60);
+        let clip = Duration::from_secs(15 * 60);
+        let minutes = |minutes: u64| Some(Duration::from_secs(minutes * 60));
+
+        assert_eq!(
+            categorize(&video, minutes(121), movie, clip),
+            Category::Movie
+        );
+        assert_eq!(
+            categorize(&video, minutes(70), movie, clip),
+            Category::Movie
+        );
+        assert_eq!(
+            categorize(&video, minutes(42), movie, clip),
+            Category::Episode
+        );
+        assert_eq!(categorize(&video, minutes(3), movie, clip), Category::Clip);
+        assert_eq!(categorize(&video, None, movie, clip), Category::Unknown);
+
+        let album = [
+            stream(StreamKind::Audio, false),
+            stream(StreamKind::Video, true),
+        ];
+        assert_eq!(
+            categorize(&album, minutes(80), movie, clip),
+            Category::Music
+        );
+        assert_eq!(categorize(&[], minutes(80), movie, clip), Category::Unknown);
+    }
+
+    #[test]
+    fn test_sar_conflict() {
+        let sar = |num, den| ffi::AVRational { num, den };
+        assert!(sar_conflict(sar(1, 1), sar(4, 3)));
+        assert!(!sar_conflict(sar(1, 1), sar(1, 1)));
+        assert!(!sar_conflict(sar(32, 27), sar(64, 54)));
+        assert!(!sar_conflict(sar(0, 1), sar(4, 3)));
+        assert!(!sar_conflict(sar(4, 3), sar(0, 0)));
+    }
+
+    #[test]
+    fn test_frame_sizes() {
+        assert_eq!(frame_sizes(&[]), None);
+        assert_eq!(
+            frame_sizes(&[310_000, 12_000, 20_000, 26_000]),
+            Some(FrameSizes {
+                min: 12_000,
+                max: 310_000,
+                average: 92_000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_count_editions() {
+        let tags = ["Theatrical", "Theatrical", "Extended ", "", "Extended"];
+        assert_eq!(count_editions(tags.into_iter().map(String::from)), 2);
+        assert_eq!(count_editions(std::iter::empty()), 0);
+    }
+
+    #[test]
+    fn test_input_format_flags() {
+        assert_eq!(input_format_flags(true, 0, 0), vec!["seekable"]);
+        assert_eq!(
+            input_format_flags(true, ffi::AVFMTCTX_UNSEEKABLE, ffi::AVFMT_TS_DISCONT),
+            vec!["live", "discontinuous-timestamps"]
+        );
+        assert!(input_format_flags(false, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100MB"), Ok(100_000_000));
+        assert_eq!(parse_size("512 kb"), Ok(512_000));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("10TB").is_err());
+        assert!(parse_size("0").is_err());
+    }
+
+    #[test]
+    fn test_split_records() {
+        let records: Vec<String> = ["aaaa", "bb", "cccccccccc", "d"]
+            .iter()
+            .map(|record| record.to_string())
+            .collect();
+        let utf8 = &OutputEncoding::Utf8;
+        assert_eq!(
+            split_records(&records, 0, 8, utf8),
+            vec![&records[0..2], &records[2..3], &records[3..4]]
+        );
+        assert_eq!(split_records(&records, 3, 8, utf8).len(), 4);
+        assert_eq!(split_records(&[], 0, 8, utf8), vec![&[] as &[String]]);
+    }
+
+    #[test]
+    fn test_split_records_encoded() {
+        // 4 bytes in UTF-8, but a 9 byte escape in Latin-1
+        let records = vec![String::from("\u{1F600}"), String::from("b")];
+        assert_eq!(
+            split_records(&records, 0, 8, &OutputEncoding::Utf8).len(),
+            1
+        );
+        assert_eq!(
+            split_records(&records, 0, 8, &OutputEncoding::Latin1).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path(Path::new("out/report.json"), 1),
+            PathBuf::from("out/report.001.json")
+        );
+        assert_eq!(
+            split_path(Path::new("report"), 12),
+            PathBuf::from("report.012")
+        );
+    }
+
+    #[test]
+    fn test_max_interleave_gap() {
+        let interleaved = [
+            (true, 0.0),
+            (false, 0.0),
+            (true, 0.5),
+            (false, 0.4),
+            (true, 1.0),
+        ];
+        assert_eq!(max_interleave_gap(interleaved), Some(0.6));
+
+        // Every video packet first, the audio only starts once they ran out
+        let sequential = [
+            (true, 0.0),
+            (true, 30.0),
+            (true, 60.0),
+            (false, 0.0),
+            (false, 60.0),
+        ];
+        assert_eq!(max_interleave_gap(sequential), Some(60.0));
+
+        assert_eq!(max_interleave_gap([(true, 0.0), (true, 1.0)]), None);
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(spread(&[]), None);
+        assert_eq!(spread(&[1.5]), None);
+        assert_eq!(spread(&[0.021, -0.019, 0.0]), Some(0.04));
+    }
+
+    #[test]
+    fn test_strip_versions() {
+        assert_eq!(strip_versions("Lavf58.29.100"), "Lavf");
+        assert_eq!(
+            strip_versions("libebml v1.4.2 + libmatroska v1.6.4"),
+            "libebml + libmatroska"
+        );
+        assert_eq!(
+            strip_versions("mkvmerge v52.0.0 ('Secret For The Mad') 64-bit"),
+            "mkvmerge ('Secret For The Mad') 64-bit"
+        );
+        assert_eq!(strip_versions("HandBrake 1.5.1 2022011000"), "HandBrake");
+        assert_eq!(strip_versions("x264 core 164"), "x264 core");
+    }
+
+    #[test]
+    fn test_frame_count_divergence() {
+        let frame_count = |declared, estimated| FrameCount {
+            declared,
+            estimated,
+        };
+
+        assert!(!frame_count_diverges(&frame_count(1440, 1440), 1.0));
+        assert!(!frame_count_diverges(&frame_count(1439, 1440), 0.0));
+        assert!(!frame_count_diverges(&frame_count(143_000, 144_000), 1.0));
+        assert!(frame_count_diverges(&frame_count(72_000, 144_000), 1.0));
+        assert!(frame_count_diverges(&frame_count(1500, 1440), 1.0));
+    }
+
+    #[test]
+    fn test_bits_per_pixel() {
+        let hd = bits_per_pixel(5_000_000, (1920, 1080), 24.0).unwrap();
+        assert!((hd - 0.1005).abs() < 0.0001);
+
+        assert_eq!(bits_per_pixel(0, (1920, 1080), 24.0), None);
+        assert_eq!(bits_per_pixel(5_000_000, (1920, 1080), 0.0), None);
+    }
+
+    #[test]
+    fn test_modified_recently() {
+        let now = SystemTime::now();
+        let window = Duration::from_secs(60);
+
+        assert!(modified_recently(now - Duration::from_secs(5), now, window));
+        assert!(modified_recently(now + Duration::from_secs(5), now, window));
+        assert!(!modified_recently(
+            now - Duration::from_secs(3600),
+            now,
+            window
+        ));
+    }
+
+    #[test]
+    fn test_latin1_encoding() {
+        let report = "Am\u{e9}lie.mkv";
+        assert_eq!(
+            encode_report(report, &OutputEncoding::Latin1),
+            b"Am\xe9lie.mkv".to_vec()
+        );
+        assert_eq!(
+            encode_report(report, &OutputEncoding::Utf8),
+            report.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_latin1_escapes_unrepresentable() {
+        let report = "\u{5343}\u{5c0b}.mkv";
+        assert_eq!(
+            encode_report(report, &OutputEncoding::Latin1),
+            br"\u{5343}\u{5C0B}.mkv".to_vec()
+        );
+    }
+}
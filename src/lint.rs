@@ -0,0 +1,223 @@
+use crate::report::{MediaReport, StreamKind};
+
+/// Extensions of containers that are expected to carry a video stream
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "avi", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "ogv", "ts", "vob", "webm",
+    "wmv",
+];
+
+/// Extensions that are commonly used for a container ffmpeg doesn't list them
+/// under, keyed by the extension and valued by the ffmpeg format names
+const EXTENSION_ALIASES: &[(&str, &[&str])] = &[
+    ("m4v", &["mov", "mp4"]),
+    ("m2ts", &["mpegts"]),
+    ("ts", &["mpegts"]),
+    ("mpg", &["mpeg"]),
+];
+
+/// A problem found by `--lint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// ffmpeg couldn't analyze the file, only listed with
+    /// `--treat-errors-as-entries`
+    Unreadable,
+    /// The container holds no streams at all
+    ZeroStreams,
+    /// A video container without a (non cover art) video stream
+    NoVideo,
+    /// A video stream without any accompanying audio
+    NoAudio,
+    /// The file extension doesn't belong to the detected container format
+    MislabeledContainer,
+}
+
+impl Problem {
+    /// Category name printed in the problem list
+    pub fn category(&self) -> &'static str {
+        match self {
+            Problem::Unreadable => "unreadable",
+            Problem::ZeroStreams => "zero-streams",
+            Problem::NoVideo => "no-video",
+            Problem::NoAudio => "no-audio",
+            Problem::MislabeledContainer => "mislabeled-container",
+        }
+    }
+
+    /// Why the report has the problem, printed with `--explain`
+    pub fn reason(&self, report: &MediaReport) -> String {
+        let extension = report
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match self {
+            Problem::Unreadable => report.error.clone().unwrap_or_default(),
+            Problem::ZeroStreams => String::from("the container holds no streams"),
+            Problem::NoVideo => format!("extension .{}, but no video stream", extension),
+            Problem::NoAudio => String::from("video stream, but no audio stream"),
+            Problem::MislabeledContainer => {
+                format!("extension .{}, detected {}", extension, report.format_name)
+            }
+        }
+    }
+}
+
+/// Run every lint check against a report, returning the problems found
+pub fn lint(report: &MediaReport) -> Vec<Problem> {
+    // The placeholder of a failed analysis has no streams to check
+    if report.error.is_some() {
+        return vec![Problem::Unreadable];
+    }
+    if report.streams.is_empty() {
+        return vec![Problem::ZeroStreams];
+    }
+
+    let extension = report
+        .path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    let mut problems = Vec::new();
+    let has_video = report
+        .streams
+        .iter()
+        .any(|stream| stream.kind == StreamKind::Video && !stream.attached_picture);
+    let has_audio = report
+        .streams
+        .iter()
+        .any(|stream| stream.kind == StreamKind::Audio);
+
+    if let Some(extension) = &extension {
+        if !has_video && VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            problems.push(Problem::NoVideo);
+        }
+
+        if is_mislabeled(report, extension) {
+            problems.push(Problem::MislabeledContainer);
+        }
+    }
+
+    if has_video && !has_audio {
+        problems.push(Problem::NoAudio);
+    }
+
+    problems
+}
+
+/// Checks the extension against the detected format. Formats that don't
+/// advertise any extensions can't be judged and are never mislabeled
+fn is_mislabeled(report: &MediaReport, extension: &str) -> bool {
+    if report.format_extensions.is_empty() {
+        return false;
+    }
+
+    if report
+        .format_extensions
+        .iter()
+        .any(|known| known == extension)
+    {
+        return false;
+    }
+
+    !EXTENSION_ALIASES.iter().any(|(alias, formats)| {
+        *alias == extension
+            && report
+                .format_name
+                .split(',')
+                .any(|name| formats.contains(&name))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::StreamReport;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn stream(kind: StreamKind) -> StreamReport {
+        StreamReport {
+            kind,
+            codec: String::from("codec"),
+            ..StreamReport::default()
+        }
+    }
+
+    fn report(path: &str, format_name: &str, extensions: &[&str]) -> MediaReport {
+        MediaReport {
+            path: PathBuf::from(path),
+            format_name: String::from(format_name),
+            format_extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            duration: Some(Duration::from_secs(60)),
+            streams: vec![stream(StreamKind::Video), stream(StreamKind::Audio)],
+            ..MediaReport::default()
+        }
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let report = report("a.mkv", "matroska,webm", &["mkv", "mka", "webm"]);
+        assert_eq!(lint(&report), vec![]);
+    }
+
+    #[test]
+    fn test_zero_streams() {
+        let mut report = report("a.mkv", "matroska,webm", &["mkv"]);
+        report.streams.clear();
+        assert_eq!(lint(&report), vec![Problem::ZeroStreams]);
+    }
+
+    #[test]
+    fn test_unreadable() {
+        let failed = MediaReport::failed(
+            PathBuf::from("a.mkv"),
+            0,
+            String::from("ffmpeg could not open the file"),
+        );
+        assert_eq!(lint(&failed), vec![Problem::Unreadable]);
+        assert_eq!(
+            Problem::Unreadable.reason(&failed),
+            "ffmpeg could not open the file"
+        );
+    }
+
+    #[test]
+    fn test_missing_streams() {
+        let mut report = report("a.mp4", "mov,mp4,m4a", &["mov", "mp4"]);
+        report.streams = vec![stream(StreamKind::Video)];
+        assert_eq!(lint(&report), vec![Problem::NoAudio]);
+
+        let mut cover_art = stream(StreamKind::Video);
+        cover_art.attached_picture = true;
+        report.streams = vec![stream(StreamKind::Audio), cover_art];
+        assert_eq!(lint(&report), vec![Problem::NoVideo]);
+    }
+
+    #[test]
+    fn test_audio_container_without_video() {
+        let mut report = report("a.flac", "flac", &["flac"]);
+        report.streams = vec![stream(StreamKind::Audio)];
+        assert_eq!(lint(&report), vec![]);
+    }
+
+    #[test]
+    fn test_mislabeled_container() {
+        let report = report("a.mkv", "avi", &["avi"]);
+        assert_eq!(lint(&report), vec![Problem::MislabeledContainer]);
+    }
+
+    #[test]
+    fn test_reason() {
+        let report = report("a.MKV", "avi", &["avi"]);
+        assert_eq!(
+            Problem::MislabeledContainer.reason(&report),
+            "extension .mkv, detected avi"
+        );
+    }
+
+    #[test]
+    fn test_extension_alias() {
+        let report = report("a.M4V", "mov,mp4,m4a", &["mov", "mp4", "m4a"]);
+        assert_eq!(lint(&report), vec![]);
+    }
+}
@@ -0,0 +1,150 @@
+/// Decimal separator and thousands grouping of the numbers in the human
+/// output. The machine formats always use the plain C notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub grouping: Option<char>,
+}
+
+/// No-break space, which keeps a grouped number on one line
+const NO_BREAK_SPACE: char = '\u{a0}';
+
+/// Languages writing `1.234,5`
+const COMMA_DECIMAL_DOT_GROUPING: &[&str] = &[
+    "da", "de", "el", "es", "id", "it", "nl", "pt", "ro", "sl", "sr", "tr",
+];
+
+/// Languages writing `1 234,5`
+const COMMA_DECIMAL_SPACE_GROUPING: &[&str] = &[
+    "bg", "cs", "et", "fi", "fr", "hu", "lt", "lv", "nb", "nn", "no", "pl", "ru", "sk", "sv", "uk",
+];
+
+impl NumberFormat {
+    /// `1234.5`, the notation of the `C` and `POSIX` locales
+    pub const C: NumberFormat = NumberFormat {
+        decimal: '.',
+        grouping: None,
+    };
+
+    /// Conventions of a locale name such as `de_DE.UTF-8` or `fr`, only the
+    /// language is considered. English gets `1,234.5` and anything unknown
+    /// the C notation
+    pub fn for_locale(name: &str) -> NumberFormat {
+        let language = name
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+
+        let (decimal, grouping) = match language.as_str() {
+            "en" => ('.', ','),
+            language if COMMA_DECIMAL_DOT_GROUPING.contains(&language) => (',', '.'),
+            language if COMMA_DECIMAL_SPACE_GROUPING.contains(&language) => (',', NO_BREAK_SPACE),
+            _ => return NumberFormat::C,
+        };
+
+        NumberFormat {
+            decimal,
+            grouping: Some(grouping),
+        }
+    }
+
+    /// Rewrite every number in an already formatted value, e.g. `12345.67 KB/s`
+    /// becomes `12.345,67 KB/s` with German conventions
+    pub fn localize(&self, text: &str) -> String {
+        if *self == NumberFormat::C {
+            return text.to_owned();
+        }
+
+        let mut localized = String::with_capacity(text.len());
+        let mut number = String::new();
+        for c in text.chars() {
+            if c.is_ascii_digit() || (c == '.' && !number.is_empty()) {
+                number.push(c);
+                continue;
+            }
+            localized.push_str(&self.localize_number(&number));
+            number.clear();
+            localized.push(c);
+        }
+        localized.push_str(&self.localize_number(&number));
+
+        localized
+    }
+
+    fn localize_number(&self, number: &str) -> String {
+        let (integer, fraction) = match number.split_once('.') {
+            // A trailing dot ends a sentence rather than starting a fraction
+            Some((integer, "")) => return format!("{}.", self.group(integer)),
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (number, None),
+        };
+
+        match fraction {
+            Some(fraction) => format!("{}{}{}", self.group(integer), self.decimal, fraction),
+            None => self.group(integer),
+        }
+    }
+
+    /// Insert the grouping separator between every three integer digits
+    fn group(&self, digits: &str) -> String {
+        let separator = match self.grouping {
+            Some(separator) => separator,
+            None => return digits.to_owned(),
+        };
+
+        // Only ever called with ASCII digits, so every chunk is valid UTF-8
+        let groups: Vec<&str> = digits
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .filter_map(|group| std::str::from_utf8(group).ok())
+            .collect();
+        groups.join(separator.encode_utf8(&mut [0; 4]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_for_locale() {
+        assert_eq!(NumberFormat::for_locale("C"), NumberFormat::C);
+        assert_eq!(NumberFormat::for_locale("POSIX"), NumberFormat::C);
+        assert_eq!(
+            NumberFormat::for_locale("de_DE.UTF-8"),
+            NumberFormat {
+                decimal: ',',
+                grouping: Some('.')
+            }
+        );
+        assert_eq!(
+            NumberFormat::for_locale("fr"),
+            NumberFormat {
+                decimal: ',',
+                grouping: Some(NO_BREAK_SPACE)
+            }
+        );
+    }
+
+    #[test]
+    fn test_localize() {
+        let german = NumberFormat::for_locale("de");
+        assert_eq!(german.localize("12345.67 KB/s"), "12.345,67 KB/s");
+        assert_eq!(german.localize("15.34 GB"), "15,34 GB");
+        assert_eq!(
+            german.localize("1234567 declared, 999 from duration"),
+            "1.234.567 declared, 999 from duration"
+        );
+
+        let english = NumberFormat::for_locale("en_US");
+        assert_eq!(
+            english.localize("0.480s (clean start)"),
+            "0.480s (clean start)"
+        );
+        assert_eq!(english.localize("12000 B/s"), "12,000 B/s");
+
+        assert_eq!(NumberFormat::C.localize("12000.5"), "12000.5");
+    }
+}
@@ -0,0 +1,327 @@
+//! Restore verification manifest, written by `--manifest-out` and checked by
+//! `--verify`. Version 1 is a text file with one tab separated line per file:
+//!
+//! ```text
+//! # media-server-diff manifest v1
+//! <path> <size> <duration> <structure> <checksum>
+//! ```
+//!
+//! - `path`: relative to the scanned root, with backslashes, tabs and
+//!   newlines escaped as `\\`, `\t` and `\n`, and a leading `#` as `\#` so
+//!   the line isn't taken for a comment
+//! - `size`: in bytes
+//! - `duration`: in whole microseconds, or `-` when unknown
+//! - `structure`: see `MediaReport::structural_hash`
+//! - `checksum`: hex SHA-256 of the file contents, or `-` when the manifest
+//!   was written without `--checksum`
+//!
+//! Entries are sorted by path. Blank lines and further `#` lines are ignored.
+
+use crate::report::{format_optional_duration, MediaReport};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Version of the manifest format written by `render`
+pub const VERSION: u32 = 1;
+/// First line of every manifest, followed by the version number
+const HEADER_PREFIX: &str = "# media-server-diff manifest v";
+/// Placeholder for an unknown duration or a checksum that wasn't computed
+const NONE: &str = "-";
+
+/// A single file of the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path relative to the scanned root
+    pub path: PathBuf,
+    pub size: u64,
+    pub duration: Option<Duration>,
+    pub structure: String,
+    pub checksum: Option<String>,
+}
+
+impl Entry {
+    pub fn new(root: &Path, report: &MediaReport, checksum: Option<String>) -> Entry {
+        Entry {
+            path: report.relative_path(root).to_path_buf(),
+            size: report.size,
+            duration: report.duration,
+            structure: report.structural_hash(),
+            checksum,
+        }
+    }
+}
+
+/// Render the entries as a manifest of the current version
+pub fn render(entries: &[Entry]) -> String {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut manifest = format!("{}{}\n", HEADER_PREFIX, VERSION);
+    for entry in sorted {
+        manifest.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            escape_path(&entry.path.to_string_lossy()),
+            entry.size,
+            entry
+                .duration
+                .map(|duration| duration.as_micros().to_string())
+                .as_deref()
+                .unwrap_or(NONE),
+            entry.structure,
+            entry.checksum.as_deref().unwrap_or(NONE)
+        ));
+    }
+
+    manifest
+}
+
+/// Parse a manifest, rejecting versions this build doesn't understand
+pub fn parse(contents: &str) -> Result<Vec<Entry>, String> {
+    let mut lines = contents.lines().enumerate();
+
+    let version = lines
+        .next()
+        .and_then(|(_, header)| header.strip_prefix(HEADER_PREFIX))
+        .ok_or_else(|| String::from("not a media-server-diff manifest"))?;
+    if version.trim() != VERSION.to_string() {
+        return Err(format!("unsupported manifest version {}", version.trim()));
+    }
+
+    let mut entries = Vec::new();
+    for (index, line) in lines {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || format!("line {}: invalid entry", index + 1);
+        let columns: Vec<&str> = line.split('\t').collect();
+        if let [path, size, duration, structure, checksum] = columns[..] {
+            entries.push(Entry {
+                path: PathBuf::from(unescape_path(path)),
+                size: size.parse().map_err(|_| invalid())?,
+                duration: match duration {
+                    NONE => None,
+                    micros => Some(Duration::from_micros(
+                        micros.parse().map_err(|_| invalid())?,
+                    )),
+                },
+                structure: structure.to_owned(),
+                checksum: Some(checksum)
+                    .filter(|checksum| *checksum != NONE)
+                    .map(String::from),
+            });
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Compare the entries of a restored tree against the manifest, returning
+/// a line per difference. `unreadable` holds the relative paths of the
+/// restored files ffmpeg couldn't open, which have no entry of their own.
+/// Checksums are only compared when both sides have one
+pub fn verify(expected: &[Entry], actual: &[Entry], unreadable: &[PathBuf]) -> Vec<String> {
+    let actual: BTreeMap<&Path, &Entry> = actual
+        .iter()
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    let unreadable: BTreeSet<&Path> = unreadable.iter().map(PathBuf::as_path).collect();
+    let mut problems = Vec::new();
+
+    for entry in expected {
+        let path = entry.path.to_string_lossy();
+        let restored = match actual.get(entry.path.as_path()) {
+            Some(restored) => restored,
+            None if unreadable.contains(entry.path.as_path()) => {
+                problems.push(format!("{}: unreadable", path));
+                continue;
+            }
+            None => {
+                problems.push(format!("{}: missing", path));
+                continue;
+            }
+        };
+
+        if restored.size != entry.size {
+            problems.push(format!(
+                "{}: size changed from {} to {}",
+                path, entry.size, restored.size
+            ));
+        }
+        if restored.duration != entry.duration {
+            problems.push(format!(
+                "{}: duration changed from {} to {}",
+                path,
+                format_optional_duration(entry.duration.as_ref()),
+                format_optional_duration(restored.duration.as_ref())
+            ));
+        }
+        if restored.structure != entry.structure {
+            problems.push(format!("{}: structure changed", path));
+        }
+        if let (Some(expected), Some(restored)) = (&entry.checksum, &restored.checksum) {
+            if expected != restored {
+                problems.push(format!("{}: checksum mismatch", path));
+            }
+        }
+    }
+
+    let listed: BTreeSet<&Path> = expected.iter().map(|entry| entry.path.as_path()).collect();
+    let restored: BTreeSet<&Path> = actual.keys().chain(&unreadable).copied().collect();
+    for path in restored.into_iter().filter(|path| !listed.contains(path)) {
+        problems.push(format!("{}: not in manifest", path.to_string_lossy()));
+    }
+
+    problems
+}
+
+/// Hex SHA-256 of the full contents of a file
+pub fn checksum(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn escape_path(path: &str) -> String {
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n");
+    if escaped.starts_with('#') {
+        format!("\\{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn unescape_path(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(path: &str, size: u64, checksum: Option<&str>) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            size,
+            duration: Some(Duration::from_millis(5400500)),
+            structure: String::from("0123456789abcdef"),
+            checksum: checksum.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![
+            entry("tv/Show\tName/S01E01.mkv", 2_700_250_000, Some("ab12")),
+            Entry {
+                duration: None,
+                ..entry("movies/C:\\Heat.mkv", 15_340_500_000, None)
+            },
+        ];
+
+        let rendered = render(&entries);
+        assert_eq!(
+            rendered,
+            "# media-server-diff manifest v1\
+             \nmovies/C:\\\\Heat.mkv\t15340500000\t-\t0123456789abcdef\t-\
+             \ntv/Show\\tName/S01E01.mkv\t2700250000\t5400500000\t0123456789abcdef\tab12\n"
+        );
+
+        let mut parsed = parse(&rendered).unwrap();
+        parsed.reverse();
+        assert_eq!(parsed, entries);
+    }
+
+    #[test]
+    fn test_round_trip_leading_hash() {
+        let entries = vec![
+            entry("#1 Hits.mkv", 100, None),
+            entry("music/#2.mkv", 100, None),
+        ];
+
+        let rendered = render(&entries);
+        assert!(rendered.contains("\n\\#1 Hits.mkv\t"));
+        assert_eq!(parse(&rendered).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        assert!(parse("# media-server-diff manifest v2\n").is_err());
+        assert!(parse("path\tsize\n").is_err());
+        assert!(parse("# media-server-diff manifest v1\nbroken\n").is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let expected = vec![
+            entry("a.mkv", 100, Some("aa")),
+            entry("b.mkv", 100, None),
+            entry("c.mkv", 100, Some("cc")),
+        ];
+        let actual = vec![
+            entry("a.mkv", 100, Some("ff")),
+            entry("c.mkv", 90, None),
+            entry("d.mkv", 100, None),
+        ];
+
+        assert_eq!(
+            verify(&expected, &actual, &[]),
+            vec![
+                "a.mkv: checksum mismatch",
+                "b.mkv: missing",
+                "c.mkv: size changed from 100 to 90",
+                "d.mkv: not in manifest",
+            ]
+        );
+        assert_eq!(verify(&expected, &expected, &[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_verify_unreadable() {
+        let expected = vec![entry("a.mkv", 100, None), entry("b.mkv", 100, None)];
+        let actual = vec![entry("a.mkv", 100, None)];
+        let unreadable = vec![PathBuf::from("b.mkv"), PathBuf::from("c.mkv")];
+
+        assert_eq!(
+            verify(&expected, &actual, &unreadable),
+            vec!["b.mkv: unreadable", "c.mkv: not in manifest"]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Phases of a scan timed for `--profile-stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Discovery,
+    Open,
+    Streams,
+    Decode,
+    Hashing,
+}
+
+const PHASES: [Phase; 5] = [
+    Phase::Discovery,
+    Phase::Open,
+    Phase::Streams,
+    Phase::Decode,
+    Phase::Hashing,
+];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// Nanoseconds spent and number of calls per phase, shared by the rayon
+/// workers. Always collected, the cost is a clock read per phase
+static NANOS: [AtomicU64; PHASES.len()] = [ZERO; PHASES.len()];
+static CALLS: [AtomicU64; PHASES.len()] = [ZERO; PHASES.len()];
+
+impl Phase {
+    fn label(&self) -> &'static str {
+        match self {
+            Phase::Discovery => "discovery",
+            Phase::Open => "ffmpeg open",
+            Phase::Streams => "stream analysis",
+            Phase::Decode => "packet reading",
+            Phase::Hashing => "hashing",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Run `work`, adding its duration to the totals of `phase`
+pub fn time<T>(phase: Phase, work: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = work();
+    record(phase, start.elapsed());
+    result
+}
+
+fn record(phase: Phase, elapsed: Duration) {
+    let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    NANOS[phase.index()].fetch_add(nanos, Ordering::Relaxed);
+    CALLS[phase.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Render the breakdown of every phase that ran. Times are summed over the
+/// worker threads, so on a parallel scan they add up to more than wall time
+pub fn render() -> String {
+    let mut breakdown = String::from("Profile (time summed over worker threads):\n");
+    for phase in PHASES {
+        let calls = CALLS[phase.index()].load(Ordering::Relaxed);
+        if calls == 0 {
+            continue;
+        }
+
+        let total = Duration::from_nanos(NANOS[phase.index()].load(Ordering::Relaxed));
+        let mean = total / u32::try_from(calls).unwrap_or(u32::MAX);
+        breakdown.push_str(&format!(
+            "\t{:<16} {:>10.3}s total {:>8} calls {:>10.3}ms mean\n",
+            phase.label(),
+            total.as_secs_f64(),
+            calls,
+            mean.as_secs_f64() * 1000.0
+        ));
+    }
+    breakdown
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_time() {
+        assert_eq!(time(Phase::Hashing, || 42), 42);
+        record(Phase::Hashing, Duration::from_millis(10));
+
+        let rendered = render();
+        assert!(rendered.contains("\thashing "));
+        assert!(!rendered.contains("discovery"));
+    }
+}
@@ -0,0 +1,1206 @@
+use crate::locale::NumberFormat;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Group name used by `group_by_codec` for files without a video stream
+pub const AUDIO_ONLY_GROUP: &str = "audio-only";
+
+/// Group name used by `group_by_codec` for files that couldn't be analyzed
+pub const ERROR_GROUP: &str = "error";
+
+/// Structured description of a single media file, produced by the analysis
+/// pass and consumed by the renderers and checks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaReport {
+    /// Path of the file as discovered under the root directory
+    pub path: PathBuf,
+    /// Short name of the detected container format, e.g. `matroska,webm`
+    pub format_name: String,
+    /// File extensions ffmpeg associates with the detected container format
+    pub format_extensions: Vec<String>,
+    /// Capabilities of the input such as `seekable` or `live`, only
+    /// collected with `--format-flags`
+    #[serde(deserialize_with = "interned_labels")]
+    pub format_flags: Option<Vec<&'static str>>,
+    /// Application that muxed the file, only collected with `--muxer-info`
+    pub muxer: Option<String>,
+    /// ffmpeg's confidence in the detected format out of 100, only collected
+    /// with `--probe-score` or `--min-probe-score`
+    pub probe_score: Option<i32>,
+    /// `None` when ffmpeg couldn't determine a duration at all, which is
+    /// different from a file that is zero seconds long
+    pub duration: Option<Duration>,
+    /// The duration was estimated from a partial read with `--sample-seconds`
+    pub duration_sampled: bool,
+    pub bit_rate: i64,
+    /// Size of the file in bytes
+    pub size: u64,
+    pub streams: Vec<StreamReport>,
+    /// Alternate cuts such as theatrical and extended, counted as the
+    /// distinct `edition` tags of the chapters. Zero when no chapter has one
+    pub editions: usize,
+    /// Index of the video stream picked to represent the file
+    pub best_video: Option<usize>,
+    /// Index of the audio stream picked to represent the file
+    pub best_audio: Option<usize>,
+    /// Color details of the video stream, only collected with `--color-info`
+    pub color: Option<ColorInfo>,
+    /// Results of the packet reading checks, only collected with `--decode`
+    pub packets: Option<PacketReport>,
+    /// Frame counts of the video stream, only collected with `--check-frame-count`
+    pub frame_count: Option<FrameCount>,
+    /// Bits spent per pixel of the video stream, only collected with `--efficiency`
+    pub efficiency: Option<f64>,
+    /// Seconds between the earliest and latest starting audio or video
+    /// stream, only collected with `--start-spread`
+    pub start_spread: Option<f64>,
+    /// Likely kind of content, only collected with `--categorize`
+    pub category: Option<Category>,
+    /// Sample aspect ratios of the video stream, only set by `--check-aspect`
+    /// when the container and the codec disagree
+    pub sample_aspect: Option<SampleAspect>,
+    /// Problems flagged by the opt-in checks
+    pub markers: Vec<Marker>,
+    /// Why the file couldn't be analyzed, only set on the placeholder entries
+    /// of `--treat-errors-as-entries`
+    pub error: Option<String>,
+}
+
+/// A discovered file that ffmpeg couldn't analyze
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportError {
+    pub path: PathBuf,
+    /// ffmpeg's description of the failure
+    pub reason: String,
+}
+
+/// Frame count the video stream declares next to the one implied by its
+/// duration and frame rate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameCount {
+    pub declared: u64,
+    pub estimated: u64,
+}
+
+/// A problem flagged by one of the opt-in checks, along with the values
+/// that triggered it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Marker {
+    /// Short upper case name of the problem, e.g. `BLANK-VIDEO`
+    pub label: &'static str,
+    pub reason: String,
+}
+
+/// A single stream within a media file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamReport {
+    pub kind: StreamKind,
+    /// ffmpeg's short codec name, e.g. `hevc`
+    pub codec: String,
+    /// Descriptive codec name, only collected with `--codec-long-name`
+    pub codec_long_name: Option<String>,
+    /// Cover art and other still images carried as a video stream
+    pub attached_picture: bool,
+    /// Width and height in pixels, only known for video streams
+    pub resolution: Option<(u32, u32)>,
+    /// `language` tag of the stream, e.g. `eng`
+    pub language: Option<String>,
+    /// `title` tag of the stream, e.g. `Commentary`
+    pub title: Option<String>,
+}
+
+/// A single field of two reports of the same file, the value is `None` on
+/// the side where the field doesn't exist at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+impl FieldDiff {
+    /// The two reports disagree on this field
+    pub fn differs(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+/// Media type of a stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+    #[default]
+    Unknown,
+}
+
+/// Kind of content a file most likely holds, guessed by `--categorize`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Category {
+    Movie,
+    Episode,
+    Clip,
+    Music,
+    /// Neither audio only nor of a known duration
+    Unknown,
+}
+
+impl Category {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Movie => "movie",
+            Category::Episode => "episode",
+            Category::Clip => "clip",
+            Category::Music => "music",
+            Category::Unknown => "unknown",
+        }
+    }
+}
+
+/// Bit depth and HDR mastering metadata of a video stream
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorInfo {
+    pub bits_per_raw_sample: Option<i32>,
+    pub mastering_display: Option<String>,
+    pub content_light_level: Option<String>,
+}
+
+/// Details gathered by reading the packets of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PacketReport {
+    /// `None` when there is no video stream or it holds no keyframe
+    pub first_keyframe: Option<FirstKeyframe>,
+    /// `None` when none of the decoded video frames carried caption data
+    pub captions: Option<Captions>,
+    /// Spacing of the keyframes, only collected with `--keyframe-cadence` and
+    /// `None` when there are too few keyframes to tell
+    pub cadence: Option<KeyframeCadence>,
+    /// Compressed sizes of the video frames, only collected with
+    /// `--frame-sizes` and `None` when the stream holds no packets
+    pub frame_sizes: Option<FrameSizes>,
+    /// How well the audio and video packets are interleaved, only collected
+    /// with `--interleave` and `None` without both an audio and video stream
+    pub interleave: Option<Interleave>,
+}
+
+/// Largest distance in seconds between the audio and the video timestamps
+/// while reading the packets in file order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Interleave {
+    pub max_gap: f64,
+    /// The gap stays below the `--max-interleave-gap`
+    pub good: bool,
+}
+
+/// Sample aspect ratios the container and the codec declare for the video
+/// stream, as `(numerator, denominator)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleAspect {
+    pub container: (i32, i32),
+    pub codec: (i32, i32),
+}
+
+impl SampleAspect {
+    /// `container 1:1 codec 4:3`
+    pub fn describe(&self) -> String {
+        format!(
+            "container {} codec {}",
+            format_ratio(self.container),
+            format_ratio(self.codec)
+        )
+    }
+}
+
+/// A sample aspect ratio as `num:den`
+pub fn format_ratio((num, den): (i32, i32)) -> String {
+    format!("{}:{}", num, den)
+}
+
+/// Compressed size of the video frames in bytes, from their packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameSizes {
+    pub min: u64,
+    pub max: u64,
+    pub average: u64,
+}
+
+/// Spacing of the keyframes in the video stream, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyframeCadence {
+    /// Median distance between two keyframes
+    pub interval: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Every distance is close enough to the median to cut segments of a
+    /// fixed length at the keyframes
+    pub regular: bool,
+}
+
+/// Closed captions embedded in the video stream, as opposed to subtitles
+/// carried in a stream of their own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Captions {
+    pub cea_608: bool,
+    pub cea_708: bool,
+}
+
+impl Captions {
+    /// Names of the caption services present
+    pub fn labels(&self) -> Vec<&'static str> {
+        let services = [(self.cea_608, "CEA-608"), (self.cea_708, "CEA-708")];
+        services
+            .iter()
+            .filter(|(present, _)| *present)
+            .map(|(_, label)| *label)
+            .collect()
+    }
+}
+
+/// Position of the first keyframe in the video stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirstKeyframe {
+    /// Seconds, in decode order, from the first video packet to the keyframe
+    pub offset: f64,
+    /// The very first video packet is the keyframe
+    pub clean_start: bool,
+}
+
+/// The labels of the reports read back from the `--cache`, each distinct
+/// one leaked once so they stay `&'static str` like those of the analysis
+fn intern(label: String) -> &'static str {
+    static LABELS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut labels = LABELS.lock().unwrap_or_else(PoisonError::into_inner);
+    match labels.get(label.as_str()) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(label.into_boxed_str());
+            labels.insert(interned);
+            interned
+        }
+    }
+}
+
+/// Read back the format flags of a cached report
+fn interned_labels<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<&'static str>>, D::Error> {
+    let labels = Option::<Vec<String>>::deserialize(deserializer)?;
+    Ok(labels.map(|labels| labels.into_iter().map(intern).collect()))
+}
+
+impl<'de> Deserialize<'de> for Marker {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Marker, D::Error> {
+        #[derive(Deserialize)]
+        struct Owned {
+            label: String,
+            reason: String,
+        }
+
+        let marker = Owned::deserialize(deserializer)?;
+        Ok(Marker {
+            label: intern(marker.label),
+            reason: marker.reason,
+        })
+    }
+}
+
+impl MediaReport {
+    /// Placeholder entry for a file that couldn't be analyzed, holding only
+    /// its path, size and the reason
+    pub fn failed(path: PathBuf, size: u64, reason: String) -> MediaReport {
+        MediaReport {
+            path,
+            size,
+            error: Some(reason),
+            ..MediaReport::default()
+        }
+    }
+
+    /// Render the textual description of the media file that can be used
+    /// to differentiate between multiple copies of the same data set that
+    /// have diverged, with the numbers written the way `numbers` describes.
+    /// With `explain` every marker is followed by the reason it was flagged
+    pub fn render(&self, numbers: &NumberFormat, explain: bool) -> String {
+        if let Some(error) = &self.error {
+            return format!(
+                "{}\n\tSize: {}\n\tERROR: {}",
+                self.path.to_string_lossy(),
+                numbers.localize(&format_size(self.size)),
+                error
+            );
+        }
+
+        let duration = match self.duration {
+            Some(duration) if self.duration_sampled => {
+                format!("~{} (sampled)", format_duration(&duration))
+            }
+            duration => format_optional_duration(duration.as_ref()),
+        };
+
+        let mut report = format!(
+            "{}\n\tDuration: {}\n\tBit rate: {}",
+            self.path.to_string_lossy(),
+            numbers.localize(&duration),
+            numbers.localize(&format_bit_rate(self.bit_rate)),
+        );
+
+        for stream in &self.streams {
+            report.push_str(&format!(
+                "\n\t{}: {}",
+                stream.kind.label(),
+                stream.codec_long_name.as_ref().unwrap_or(&stream.codec)
+            ));
+            if let Some(language) = &stream.language {
+                report.push_str(&format!(" {}", language));
+            }
+            if let Some(title) = &stream.title {
+                report.push_str(&format!(" \"{}\"", title));
+            }
+        }
+
+        if let Some(flags) = &self.format_flags {
+            report.push_str(&format!("\n\tFormat: {}", self.format_name));
+            if !flags.is_empty() {
+                report.push_str(&format!(" [{}]", flags.join(", ")));
+            }
+        }
+
+        if let Some(muxer) = &self.muxer {
+            report.push_str(&format!("\n\tMuxer: {}", muxer));
+        }
+
+        if let Some(score) = self.probe_score {
+            report.push_str(&format!("\n\tProbe score: {}/100", score));
+        }
+
+        if self.editions > 1 {
+            report.push_str(&format!("\n\tEditions: {}", self.editions));
+        }
+
+        if let Some(color) = &self.color {
+            report.push_str(&color.render());
+        }
+
+        if let Some(packets) = &self.packets {
+            report.push_str(&format!(
+                "\n\tFirstKeyframe: {}",
+                numbers.localize(&format_first_keyframe(packets.first_keyframe.as_ref()))
+            ));
+            if let Some(cadence) = &packets.cadence {
+                report.push_str(&numbers.localize(&format_cadence(cadence)));
+            }
+            if let Some(sizes) = &packets.frame_sizes {
+                report.push_str(&numbers.localize(&format!(
+                    "\n\tFrameSize: avg {} min {} max {}",
+                    format_size(sizes.average),
+                    format_size(sizes.min),
+                    format_size(sizes.max)
+                )));
+            }
+            if let Some(interleave) = &packets.interleave {
+                report.push_str(&numbers.localize(&format!(
+                    "\n\tInterleave: {} (max gap {:.2}s)",
+                    if interleave.good { "good" } else { "poor" },
+                    interleave.max_gap
+                )));
+            }
+            if let Some(captions) = &packets.captions {
+                report.push_str(&format!(
+                    "\n\tCaptions: {} present",
+                    captions.labels().join(", ")
+                ));
+            }
+        }
+
+        if let Some(frame_count) = &self.frame_count {
+            report.push_str(&numbers.localize(&format!(
+                "\n\tFrames: {} declared, {} from duration",
+                frame_count.declared, frame_count.estimated
+            )));
+        }
+
+        if let Some(efficiency) = self.efficiency {
+            report.push_str(
+                &numbers.localize(&format!("\n\tEfficiency: {:.3} bits/pixel", efficiency)),
+            );
+        }
+
+        if let Some(spread) = self.start_spread {
+            report.push_str(&numbers.localize(&format!("\n\tStartSpread: {:.3}s", spread)));
+        }
+
+        if let Some(category) = self.category {
+            report.push_str(&format!("\n\tCategory: {}", category.label()));
+        }
+
+        if let Some(aspect) = &self.sample_aspect {
+            report.push_str(&format!("\n\tSampleAspect: {}", aspect.describe()));
+        }
+
+        if !self.markers.is_empty() {
+            let labels: Vec<&str> = self.markers.iter().map(|marker| marker.label).collect();
+            report.push_str(&format!("\n\tMarkers: {}", labels.join(", ")));
+            if explain {
+                for marker in &self.markers {
+                    report.push_str(&format!("\n\t\t{}: {}", marker.label, marker.reason));
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Path of the file relative to the root it was discovered under
+    pub fn relative_path(&self, root: &Path) -> &Path {
+        self.path.strip_prefix(root).unwrap_or(&self.path)
+    }
+
+    /// Short hash of the structure of the file: the container format, the
+    /// duration and the streams. It doesn't depend on the path or the exact
+    /// bytes, so it matches between copies that hold the same content
+    pub fn structural_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.format_name.as_bytes());
+        match self.duration {
+            Some(duration) => hasher.update(duration.as_millis().to_le_bytes()),
+            None => hasher.update(b"unknown"),
+        }
+        for stream in &self.streams {
+            hasher.update(
+                format!(
+                    "\0{}\0{}\0{}\0{:?}\0{:?}\0{:?}",
+                    stream.kind.label(),
+                    stream.codec,
+                    stream.attached_picture,
+                    stream.resolution,
+                    stream.language,
+                    stream.title
+                )
+                .as_bytes(),
+            );
+        }
+
+        hasher.finalize()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// The number of editions when there is more than one
+    fn edition_count(&self) -> Option<String> {
+        Some(self.editions)
+            .filter(|editions| *editions > 1)
+            .map(|editions| editions.to_string())
+    }
+
+    /// Codec of the video stream representing the file
+    pub fn video_codec(&self) -> Option<&str> {
+        self.best_video
+            .and_then(|index| self.streams.get(index))
+            .map(|stream| stream.codec.as_str())
+    }
+
+    /// Codec of the audio stream representing the file
+    pub fn audio_codec(&self) -> Option<&str> {
+        self.best_audio
+            .and_then(|index| self.streams.get(index))
+            .map(|stream| stream.codec.as_str())
+    }
+
+    /// Compare two reports field by field, returning only the fields that
+    /// differ. Streams are matched up by their index
+    pub fn diff(a: &MediaReport, b: &MediaReport) -> Vec<FieldDiff> {
+        MediaReport::fields(a, b)
+            .into_iter()
+            .filter(FieldDiff::differs)
+            .collect()
+    }
+
+    /// Every field that exists in either report, equal or not, in the order
+    /// `diff` lists them
+    pub fn fields(a: &MediaReport, b: &MediaReport) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+
+        let fields = [
+            ("Format", a.format_name.clone(), b.format_name.clone()),
+            (
+                "Duration",
+                format_optional_duration(a.duration.as_ref()),
+                format_optional_duration(b.duration.as_ref()),
+            ),
+            (
+                "Bit rate",
+                format_bit_rate(a.bit_rate),
+                format_bit_rate(b.bit_rate),
+            ),
+            ("Size", a.size.to_string(), b.size.to_string()),
+        ];
+        for (field, value_a, value_b) in fields {
+            push_field(&mut diffs, field, Some(value_a), Some(value_b));
+        }
+        push_field(&mut diffs, "Muxer", a.muxer.clone(), b.muxer.clone());
+        push_field(&mut diffs, "Editions", a.edition_count(), b.edition_count());
+
+        for index in 0..a.streams.len().max(b.streams.len()) {
+            match (a.streams.get(index), b.streams.get(index)) {
+                (Some(stream_a), Some(stream_b)) => {
+                    let fields = [
+                        ("kind", stream_a.kind.label(), stream_b.kind.label()),
+                        ("codec", &stream_a.codec, &stream_b.codec),
+                    ];
+                    for (field, value_a, value_b) in fields {
+                        push_field(
+                            &mut diffs,
+                            &format!("Stream #{} {}", index, field),
+                            Some(value_a.to_owned()),
+                            Some(value_b.to_owned()),
+                        );
+                    }
+                    push_field(
+                        &mut diffs,
+                        &format!("Stream #{} resolution", index),
+                        stream_a.resolution.map(format_resolution),
+                        stream_b.resolution.map(format_resolution),
+                    );
+                    let tags = [
+                        ("language", &stream_a.language, &stream_b.language),
+                        ("title", &stream_a.title, &stream_b.title),
+                    ];
+                    for (tag, value_a, value_b) in tags {
+                        push_field(
+                            &mut diffs,
+                            &format!("Stream #{} {}", index, tag),
+                            value_a.clone(),
+                            value_b.clone(),
+                        );
+                    }
+                }
+                (stream_a, stream_b) => push_field(
+                    &mut diffs,
+                    &format!("Stream #{}", index),
+                    stream_a.map(StreamReport::summary),
+                    stream_b.map(StreamReport::summary),
+                ),
+            }
+        }
+
+        diffs
+    }
+}
+
+/// Bucket reports by their video codec, sorted by codec name. Files without
+/// a video stream end up in the `AUDIO_ONLY_GROUP`, files that couldn't be
+/// analyzed in the `ERROR_GROUP`
+pub fn group_by_codec(reports: &[MediaReport]) -> Vec<(&str, Vec<&MediaReport>)> {
+    let mut groups: BTreeMap<&str, Vec<&MediaReport>> = BTreeMap::new();
+    for report in reports {
+        let group = if report.error.is_some() {
+            ERROR_GROUP
+        } else {
+            report.video_codec().unwrap_or(AUDIO_ONLY_GROUP)
+        };
+        groups.entry(group).or_default().push(report);
+    }
+
+    groups.into_iter().collect()
+}
+
+/// Record a field, unless it is missing from both reports
+fn push_field(diffs: &mut Vec<FieldDiff>, field: &str, a: Option<String>, b: Option<String>) {
+    if a.is_some() || b.is_some() {
+        diffs.push(FieldDiff {
+            field: field.to_owned(),
+            a,
+            b,
+        });
+    }
+}
+
+impl StreamReport {
+    /// One line description of the stream, e.g. `Video: hevc 1920x1080`
+    pub fn summary(&self) -> String {
+        match self.resolution {
+            Some(resolution) => format!(
+                "{}: {} {}",
+                self.kind.label(),
+                self.codec,
+                format_resolution(resolution)
+            ),
+            None => format!("{}: {}", self.kind.label(), self.codec),
+        }
+    }
+}
+
+impl StreamKind {
+    /// Label used for the stream lines of the report
+    pub fn label(&self) -> &'static str {
+        match self {
+            StreamKind::Video => "Video",
+            StreamKind::Audio => "Audio",
+            StreamKind::Subtitle => "Subtitle",
+            StreamKind::Data => "Data",
+            StreamKind::Attachment => "Attachment",
+            StreamKind::Unknown => "Unknown",
+        }
+    }
+}
+
+impl ColorInfo {
+    /// Render the color section, or an empty string when the stream carries
+    /// none of the color details
+    fn render(&self) -> String {
+        let lines: Vec<String> = [
+            self.bits_per_raw_sample
+                .map(|bits| format!("Bits per raw sample: {}", bits)),
+            self.mastering_display
+                .as_ref()
+                .map(|mastering| format!("Mastering display: {}", mastering)),
+            self.content_light_level
+                .as_ref()
+                .map(|light_level| format!("Content light level: {}", light_level)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if lines.is_empty() {
+            return String::default();
+        }
+
+        let mut section = String::from("\n\tColor:");
+        for line in lines {
+            section.push_str("\n\t\t");
+            section.push_str(&line);
+        }
+
+        section
+    }
+}
+
+/// Format the first keyframe position, flagging streams that don't start on one
+fn format_first_keyframe(keyframe: Option<&FirstKeyframe>) -> String {
+    match keyframe {
+        Some(FirstKeyframe {
+            offset,
+            clean_start: true,
+        }) => format!("{:.3}s (clean start)", offset),
+        Some(FirstKeyframe {
+            offset,
+            clean_start: false,
+        }) => format!("{:.3}s (NON-KEYFRAME START)", offset),
+        None => String::from("none found"),
+    }
+}
+
+fn format_cadence(cadence: &KeyframeCadence) -> String {
+    if cadence.regular {
+        format!("\n\tSegmentable: yes (keyframes ~{:.2}s)", cadence.interval)
+    } else {
+        format!(
+            "\n\tSegmentable: no (keyframes every {:.2}-{:.2}s)",
+            cadence.min, cadence.max
+        )
+    }
+}
+
+/// Format a video resolution as `WIDTHxHEIGHT`
+fn format_resolution((width, height): (u32, u32)) -> String {
+    format!("{}x{}", width, height)
+}
+
+/// Format a base 10 bit rate number into a human readable format
+pub fn format_bit_rate(bit_rate: i64) -> String {
+    if bit_rate > 1_000_000 {
+        format!("{:.2} MB/s", (bit_rate as f64) / 1_000_000.0)
+    } else if bit_rate > 1000 {
+        format!("{:.2} KB/s", (bit_rate as f64) / 1_000.0)
+    } else {
+        format!("{} B/s", bit_rate)
+    }
+}
+
+/// Format a base 10 byte count into a human readable format
+pub fn format_size(bytes: u64) -> String {
+    if bytes > 1_000_000_000 {
+        format!("{:.2} GB", (bytes as f64) / 1_000_000_000.0)
+    } else if bytes > 1_000_000 {
+        format!("{:.2} MB", (bytes as f64) / 1_000_000.0)
+    } else if bytes > 1000 {
+        format!("{:.2} KB", (bytes as f64) / 1_000.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Format a duration that may be unknown
+pub fn format_optional_duration(duration: Option<&Duration>) -> String {
+    duration
+        .map(format_duration)
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Format the duration in a specified human readable format
+pub fn format_duration(duration: &Duration) -> String {
+    let mut result = String::default();
+
+    let minutes = duration.as_secs() / 60;
+    let hours = minutes / 60;
+    let days = hours / 24;
+
+    if days > 0 {
+        result.push_str(&format!("{:02}:", days));
+    }
+
+    if hours > 0 {
+        result.push_str(&format!("{:02}:", hours % 24));
+    }
+
+    result.push_str(&format!("{:02}:", minutes % 60));
+    result.push_str(&format!("{:02}", duration.as_secs() % 60));
+
+    if duration.subsec_nanos() as f64 * 1e-7 > 0.0 {
+        result.push_str(&format!(
+            ".{}",
+            (duration.subsec_nanos() as f64 * 1e-7) as u64
+        ));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_days_format() {
+        let days = Duration::from_secs(115197);
+        assert_eq!(format_duration(&days), String::from("01:07:59:57"));
+
+        let single_day = Duration::from_secs(60 * 60 * 24);
+        assert_eq!(format_duration(&single_day), String::from("01:00:00:00"));
+    }
+
+    #[test]
+    fn test_hours_format() {
+        let hours = Duration::from_secs(28797);
+        assert_eq!(format_duration(&hours), String::from("07:59:57"));
+
+        let single_hour = Duration::from_secs(60 * 60);
+        assert_eq!(format_duration(&single_hour), String::from("01:00:00"));
+    }
+
+    #[test]
+    fn test_minutes() {
+        let minutes = Duration::from_secs(91);
+        assert_eq!(format_duration(&minutes), String::from("01:31"));
+
+        let single_minute = Duration::from_secs(60);
+        assert_eq!(format_duration(&single_minute), String::from("01:00"));
+    }
+
+    #[test]
+    fn test_seconds() {
+        let seconds = Duration::from_secs_f32(1.12);
+        assert_eq!(format_duration(&seconds), "00:01.12");
+
+        let seconds_leftover = Duration::from_secs_f32(1.1233);
+        assert_eq!(format_duration(&seconds_leftover), "00:01.12");
+    }
+
+    #[test]
+    fn test_clean_start_keyframe() {
+        let keyframe = FirstKeyframe {
+            offset: 0.0,
+            clean_start: true,
+        };
+        assert_eq!(
+            format_first_keyframe(Some(&keyframe)),
+            "0.000s (clean start)"
+        );
+    }
+
+    #[test]
+    fn test_late_keyframe() {
+        let keyframe = FirstKeyframe {
+            offset: 0.48,
+            clean_start: false,
+        };
+        assert_eq!(
+            format_first_keyframe(Some(&keyframe)),
+            "0.480s (NON-KEYFRAME START)"
+        );
+        assert_eq!(format_first_keyframe(None), "none found");
+    }
+
+    #[test]
+    fn test_render_failed() {
+        let failed = MediaReport::failed(
+            PathBuf::from("broken.mkv"),
+            2048,
+            String::from("Invalid data found when processing input"),
+        );
+        assert_eq!(
+            failed.render(&NumberFormat::C, false),
+            "broken.mkv\n\tSize: 2.05 KB\n\tERROR: Invalid data found when processing input"
+        );
+    }
+
+    #[test]
+    fn test_cadence() {
+        let mut cadence = KeyframeCadence {
+            interval: 2.0,
+            min: 1.96,
+            max: 2.04,
+            regular: true,
+        };
+        assert_eq!(
+            format_cadence(&cadence),
+            "\n\tSegmentable: yes (keyframes ~2.00s)"
+        );
+
+        cadence.regular = false;
+        assert_eq!(
+            format_cadence(&cadence),
+            "\n\tSegmentable: no (keyframes every 1.96-2.04s)"
+        );
+    }
+
+    #[test]
+    fn test_caption_labels() {
+        let both = Captions {
+            cea_608: true,
+            cea_708: true,
+        };
+        assert_eq!(both.labels(), vec!["CEA-608", "CEA-708"]);
+        assert!(Captions::default().labels().is_empty());
+    }
+
+    #[test]
+    fn test_render() {
+        let report = MediaReport {
+            path: PathBuf::from("movies/Heat (1995).mkv"),
+            format_name: String::from("matroska,webm"),
+            format_extensions: vec![String::from("mkv")],
+            duration: Some(Duration::from_secs(10227)),
+            bit_rate: 12_000_000,
+            size: 15_340_500_000,
+            streams: vec![
+                StreamReport {
+                    kind: StreamKind::Video,
+                    codec: String::from("hevc"),
+                    resolution: Some((3840, 1600)),
+                    ..StreamReport::default()
+                },
+                StreamReport {
+                    kind: StreamKind::Audio,
+                    codec: String::from("ac3"),
+                    codec_long_name: Some(String::from("ATSC A/52A (AC-3)")),
+                    language: Some(String::from("eng")),
+                    title: Some(String::from("Commentary")),
+                    ..StreamReport::default()
+                },
+            ],
+            color: Some(ColorInfo {
+                bits_per_raw_sample: Some(10),
+                ..ColorInfo::default()
+            }),
+            ..MediaReport::default()
+        };
+
+        assert_eq!(
+            report.render(&NumberFormat::C, false),
+            "movies/Heat (1995).mkv\n\tDuration: 02:50:27\n\tBit rate: 12.00 MB/s\
+             \n\tVideo: hevc\n\tAudio: ATSC A/52A (AC-3) eng \"Commentary\"\
+             \n\tColor:\n\t\tBits per raw sample: 10"
+        );
+    }
+
+    #[test]
+    fn test_render_sampled_duration() {
+        let report = MediaReport {
+            path: PathBuf::from("a.ts"),
+            format_name: String::from("mpegts"),
+            duration: Some(Duration::from_secs(2520)),
+            duration_sampled: true,
+            bit_rate: 800,
+            ..MediaReport::default()
+        };
+
+        assert_eq!(
+            report.render(&NumberFormat::C, false),
+            "a.ts\n\tDuration: ~42:00 (sampled)\n\tBit rate: 800 B/s"
+        );
+    }
+
+    #[test]
+    fn test_render_locale() {
+        let report = MediaReport {
+            path: PathBuf::from("Heat 1.5.mkv"),
+            duration: Some(Duration::from_millis(5400500)),
+            bit_rate: 1_536_000,
+            markers: vec![Marker {
+                label: "START-SPREAD",
+                reason: String::from("the audio and video streams start 0.500s apart"),
+            }],
+            ..MediaReport::default()
+        };
+
+        assert_eq!(
+            report.render(&NumberFormat::for_locale("de_DE"), true),
+            "Heat 1.5.mkv\n\tDuration: 01:30:00,50\n\tBit rate: 1,54 MB/s\
+             \n\tMarkers: START-SPREAD\
+             \n\t\tSTART-SPREAD: the audio and video streams start 0.500s apart"
+        );
+    }
+
+    #[test]
+    fn test_render_editions() {
+        let report = MediaReport {
+            path: PathBuf::from("Blade Runner.mkv"),
+            bit_rate: 800,
+            editions: 2,
+            ..MediaReport::default()
+        };
+
+        assert_eq!(
+            report.render(&NumberFormat::C, false),
+            "Blade Runner.mkv\n\tDuration: unknown\n\tBit rate: 800 B/s\n\tEditions: 2"
+        );
+        let single = MediaReport {
+            editions: 1,
+            ..report.clone()
+        };
+        assert!(!single.render(&NumberFormat::C, false).contains("Editions"));
+        assert_eq!(
+            MediaReport::diff(&single, &report),
+            vec![field_diff("Editions", None, Some("2"))]
+        );
+    }
+
+    #[test]
+    fn test_render_explain() {
+        let report = MediaReport {
+            path: PathBuf::from("a.mkv"),
+            markers: vec![
+                Marker {
+                    label: "LOW-PROBE-SCORE",
+                    reason: String::from("ffmpeg is only 25/100 sure the file is mp3 (minimum 50)"),
+                },
+                Marker {
+                    label: "START-SPREAD",
+                    reason: String::from(
+                        "the audio and video streams start 0.250s apart (maximum 0.100s)",
+                    ),
+                },
+            ],
+            ..MediaReport::default()
+        };
+
+        let terse = report.render(&NumberFormat::C, false);
+        assert!(terse.ends_with("\n\tMarkers: LOW-PROBE-SCORE, START-SPREAD"));
+        assert_eq!(
+            report.render(&NumberFormat::C, true),
+            format!(
+                "{}\n\t\tLOW-PROBE-SCORE: ffmpeg is only 25/100 sure the file is mp3 (minimum 50)\
+                 \n\t\tSTART-SPREAD: the audio and video streams start 0.250s apart (maximum 0.100s)",
+                terse
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_sample_aspect() {
+        let report = MediaReport {
+            path: PathBuf::from("a.mkv"),
+            sample_aspect: Some(SampleAspect {
+                container: (1, 1),
+                codec: (4, 3),
+            }),
+            ..MediaReport::default()
+        };
+        assert!(report
+            .render(&NumberFormat::C, false)
+            .ends_with("\n\tSampleAspect: container 1:1 codec 4:3"));
+    }
+
+    fn diff_report(streams: Vec<StreamReport>) -> MediaReport {
+        let best_video = streams
+            .iter()
+            .position(|stream| stream.kind == StreamKind::Video && !stream.attached_picture);
+        MediaReport {
+            path: PathBuf::from("a.mkv"),
+            format_name: String::from("matroska,webm"),
+            duration: Some(Duration::from_secs(60)),
+            streams,
+            best_video,
+            ..MediaReport::default()
+        }
+    }
+
+    fn video(codec: &str, width: u32, height: u32) -> StreamReport {
+        StreamReport {
+            kind: StreamKind::Video,
+            codec: String::from(codec),
+            resolution: Some((width, height)),
+            ..StreamReport::default()
+        }
+    }
+
+    fn field_diff(field: &str, a: Option<&str>, b: Option<&str>) -> FieldDiff {
+        FieldDiff {
+            field: String::from(field),
+            a: a.map(String::from),
+            b: b.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = diff_report(vec![video("h264", 1920, 1080)]);
+        assert_eq!(MediaReport::diff(&a, &a.clone()), vec![]);
+    }
+
+    #[test]
+    fn test_diff_codec_change() {
+        let a = diff_report(vec![video("h264", 1920, 1080)]);
+        let b = diff_report(vec![video("hevc", 1920, 1080)]);
+        assert_eq!(
+            MediaReport::diff(&a, &b),
+            vec![field_diff("Stream #0 codec", Some("h264"), Some("hevc"))]
+        );
+    }
+
+    #[test]
+    fn test_diff_resolution_change() {
+        let a = diff_report(vec![video("hevc", 3840, 2160)]);
+        let b = diff_report(vec![video("hevc", 1920, 1080)]);
+        assert_eq!(
+            MediaReport::diff(&a, &b),
+            vec![field_diff(
+                "Stream #0 resolution",
+                Some("3840x2160"),
+                Some("1920x1080")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_diff_added_and_removed_streams() {
+        let audio = StreamReport {
+            kind: StreamKind::Audio,
+            codec: String::from("aac"),
+            ..StreamReport::default()
+        };
+        let a = diff_report(vec![video("h264", 1920, 1080)]);
+        let b = diff_report(vec![video("h264", 1920, 1080), audio]);
+
+        assert_eq!(
+            MediaReport::diff(&a, &b),
+            vec![field_diff("Stream #1", None, Some("Audio: aac"))]
+        );
+        assert_eq!(
+            MediaReport::diff(&b, &a),
+            vec![field_diff("Stream #1", Some("Audio: aac"), None)]
+        );
+    }
+
+    #[test]
+    fn test_diff_missing_title() {
+        let mut titled = video("h264", 1920, 1080);
+        titled.title = Some(String::from("Signs & Songs"));
+        let a = diff_report(vec![titled]);
+        let b = diff_report(vec![video("h264", 1920, 1080)]);
+
+        assert_eq!(
+            MediaReport::diff(&a, &b),
+            vec![field_diff("Stream #0 title", Some("Signs & Songs"), None)]
+        );
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let a = diff_report(vec![video("hevc", 1920, 1080)]);
+        let mut moved = a.clone();
+        moved.path = PathBuf::from("elsewhere/b.mkv");
+        moved.size = 1;
+        let transcoded = diff_report(vec![video("h264", 1920, 1080)]);
+
+        assert_eq!(a.structural_hash().len(), 16);
+        assert_eq!(a.structural_hash(), moved.structural_hash());
+        assert_ne!(a.structural_hash(), transcoded.structural_hash());
+    }
+
+    #[test]
+    fn test_group_by_codec() {
+        let audio = StreamReport {
+            kind: StreamKind::Audio,
+            codec: String::from("flac"),
+            ..StreamReport::default()
+        };
+        let mut cover_art = video("mjpeg", 500, 500);
+        cover_art.attached_picture = true;
+
+        let reports = vec![
+            diff_report(vec![video("mpeg2video", 720, 576)]),
+            diff_report(vec![audio.clone(), cover_art]),
+            diff_report(vec![video("hevc", 1920, 1080), audio]),
+            diff_report(vec![video("mpeg2video", 720, 480)]),
+        ];
+
+        let groups: Vec<(&str, usize)> = group_by_codec(&reports)
+            .into_iter()
+            .map(|(codec, reports)| (codec, reports.len()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![("audio-only", 1), ("hevc", 1), ("mpeg2video", 2)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_duration() {
+        let mut report = diff_report(vec![]);
+        report.duration = None;
+        assert!(report
+            .render(&NumberFormat::C, false)
+            .starts_with("a.mkv\n\tDuration: unknown\n"));
+
+        assert_eq!(
+            MediaReport::diff(&report, &diff_report(vec![])),
+            vec![field_diff("Duration", Some("unknown"), Some("01:00"))]
+        );
+    }
+
+    #[test]
+    fn test_megabytes() {
+        let megabytes_per_sec = 12_000_000;
+        assert_eq!(format_bit_rate(megabytes_per_sec), "12.00 MB/s")
+    }
+
+    #[test]
+    fn test_kilobytes() {
+        let kilobytes_per_sec = 12_000;
+        assert_eq!(format_bit_rate(kilobytes_per_sec), "12.00 KB/s")
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(format_size(15_340_500_000), "15.34 GB");
+        assert_eq!(format_size(2_500_000), "2.50 MB");
+        assert_eq!(format_size(999), "999 B");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let bytes_per_sec = 12;
+        assert_eq!(format_bit_rate(bytes_per_sec), "12 B/s")
+    }
+}
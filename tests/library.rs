@@ -0,0 +1,54 @@
+//! Scans through the library API instead of the command line
+
+use media_server_diff::report::{StreamKind, StreamReport};
+use media_server_diff::{report_iter, FieldDiff, MediaReport, Options};
+use std::fs;
+
+#[test]
+fn test_report_iter() {
+    let root =
+        std::env::temp_dir().join(format!("media-server-diff-{}-library", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("a.mkv"), b"").unwrap();
+    fs::write(root.join("b.mkv"), b"").unwrap();
+
+    let results: Vec<_> = report_iter(&Options::new(&root)).collect();
+    fs::remove_dir_all(&root).unwrap();
+
+    // Neither file can be opened, but both come back in path order
+    let paths: Vec<_> = results
+        .iter()
+        .map(|result| {
+            result
+                .as_ref()
+                .unwrap_err()
+                .path
+                .file_name()
+                .unwrap()
+                .to_owned()
+        })
+        .collect();
+    assert_eq!(paths, ["a.mkv", "b.mkv"]);
+}
+
+#[test]
+fn test_diff() {
+    let report = |codec: &str| MediaReport {
+        streams: vec![StreamReport {
+            kind: StreamKind::Video,
+            codec: String::from(codec),
+            ..StreamReport::default()
+        }],
+        ..MediaReport::default()
+    };
+
+    let diffs = MediaReport::diff(&report("mpeg2video"), &report("h264"));
+    assert_eq!(
+        diffs,
+        [FieldDiff {
+            field: String::from("Stream #0 codec"),
+            a: Some(String::from("mpeg2video")),
+            b: Some(String::from("h264")),
+        }]
+    );
+}
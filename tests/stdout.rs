@@ -0,0 +1,57 @@
+//! Runs the binary to check that stdout carries nothing but the report
+
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A library holding a single file ffmpeg can't open, which still gets an
+/// entry with `--treat-errors-as-entries`
+fn library(name: &str) -> PathBuf {
+    let root =
+        std::env::temp_dir().join(format!("media-server-diff-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("episode.mkv"), b"").unwrap();
+    root
+}
+
+fn stdout(name: &str, args: &[&str]) -> Vec<u8> {
+    let root = library(name);
+    let output = Command::new(env!("CARGO_BIN_EXE_media-server-diff"))
+        .arg("--root-dir")
+        .arg(&root)
+        .args(["--treat-errors-as-entries", "--output", "-"])
+        .args(args)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(output.status.success());
+    output.stdout
+}
+
+fn assert_json_lines(report: &str) {
+    assert!(!report.is_empty());
+    for line in report.lines() {
+        let record: Value = serde_json::from_str(line).unwrap();
+        assert!(record["path"].as_str().unwrap().ends_with("episode.mkv"));
+    }
+}
+
+#[test]
+fn test_json() {
+    let report = String::from_utf8(stdout("json", &["--format", "json"])).unwrap();
+    assert_json_lines(&report);
+}
+
+#[test]
+fn test_gzip() {
+    let compressed = stdout("gzip", &["--format", "json", "--compress", "gzip"]);
+    let mut report = String::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_string(&mut report)
+        .unwrap();
+    assert_json_lines(&report);
+}